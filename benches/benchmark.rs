@@ -1,6 +1,6 @@
+use std::collections::HashMap;
 use std::time::Duration;
 
-use base64::encode;
 use criterion::{black_box, criterion_group, criterion_main, Criterion};
 use image::GenericImageView;
 use lcs_png_diff::{create_table, diff};
@@ -33,29 +33,28 @@ fn long_string_lcs_table(c: &mut Criterion) {
     let after_png_1100k = image::open("tests/fixtures/backstopjs_pricing_after.png").unwrap();
     let after_w = after_png_1100k.dimensions().0;
     let before_w = before_png_1100k.dimensions().0;
-    let before_encoded_png: Vec<String> = before_png_1100k
+    // Rows are interned to integer ids the same way diff does
+    let mut ids: HashMap<&[u8], u32> = HashMap::new();
+    let mut intern = |row| {
+        let next_id = ids.len() as u32;
+        *ids.entry(row).or_insert(next_id)
+    };
+    let before_rows: Vec<u32> = before_png_1100k
         .as_bytes()
-        .to_vec()
         .chunks(before_w as usize * 4)
-        .map(encode)
+        .map(&mut intern)
         .collect();
-    let after_encoded_png: Vec<String> = after_png_1100k
+    let after_rows: Vec<u32> = after_png_1100k
         .as_bytes()
-        .to_vec()
         .chunks(after_w as usize * 4)
-        .map(encode)
+        .map(&mut intern)
         .collect();
 
     let mut group = c.benchmark_group("long_string_lcs_table");
 
     group.sample_size(10);
     group.bench_function("create_cls_table", |b| {
-        b.iter(|| {
-            create_table(
-                black_box(&before_encoded_png),
-                black_box(&after_encoded_png),
-            )
-        })
+        b.iter(|| create_table(black_box(&before_rows), black_box(&after_rows)))
     });
     group.finish();
 }
@@ -0,0 +1,120 @@
+use crate::channel::Channel;
+use crate::color::{brightness, to_rgb};
+use crate::Rows;
+use std::cmp;
+
+fn pixel<'a, S>(rows: &Rows<'a, S>, x: usize, y: usize) -> &'a [S] {
+    &rows.row(y)[x * 4..x * 4 + 4]
+}
+
+// Neighbours of (x, y) within the image, and whether (x, y) is on its edge
+fn neighbours<S>(
+    rows: &Rows<'_, S>,
+    x: usize,
+    y: usize,
+) -> (impl Iterator<Item = (usize, usize)>, bool) {
+    let width = rows.row(y).len() / 4;
+    let (x0, x2) = (x.saturating_sub(1), cmp::min(x + 1, width - 1));
+    let (y0, y2) = (y.saturating_sub(1), cmp::min(y + 1, rows.len() - 1));
+    let on_edge = x == x0 || x == x2 || y == y0 || y == y2;
+    let around = (y0..=y2)
+        .flat_map(move |ny| (x0..=x2).map(move |nx| (nx, ny)))
+        .filter(move |&n| n != (x, y));
+    (around, on_edge)
+}
+
+/// Whether the pixel at (x, y) looks like an anti-aliased edge, the way
+/// pixelmatch detects it after "Anti-aliased Pixel and Intensity Slope
+/// Detector" by V. Vysniauskas.
+///
+/// An anti-aliased pixel has both a darker and a brighter neighbour, at most
+/// two neighbours of the same brightness, and its darkest or brightest
+/// neighbour lies in a flat area of both images. `other_y` is the row of
+/// `other` that row `y` is compared against.
+pub(crate) fn antialiased<S: Channel>(
+    rows: &Rows<'_, S>,
+    x: usize,
+    y: usize,
+    other: &Rows<'_, S>,
+    other_y: usize,
+) -> bool {
+    let center = brightness(to_rgb(pixel(rows, x, y)));
+    let (around, on_edge) = neighbours(rows, x, y);
+    let mut zeroes = on_edge as u32;
+    let mut min = 0.0;
+    let mut max = 0.0;
+    let mut brightest = None;
+    let mut darkest = None;
+    for (nx, ny) in around {
+        let delta = center - brightness(to_rgb(pixel(rows, nx, ny)));
+        if delta == 0.0 {
+            zeroes += 1;
+            if zeroes > 2 {
+                return false;
+            }
+        } else if delta < min {
+            min = delta;
+            brightest = Some((nx, ny));
+        } else if delta > max {
+            max = delta;
+            darkest = Some((nx, ny));
+        }
+    }
+    let (brightest, darkest) = match (brightest, darkest) {
+        (Some(brightest), Some(darkest)) => (brightest, darkest),
+        // Not between a darker and a brighter area
+        _ => return false,
+    };
+    let in_flat_area = |(nx, ny): (usize, usize)| {
+        // Same neighbour in the other image, relative to the compared rows
+        let other_ny = (other_y + ny).checked_sub(y);
+        has_many_siblings(rows, nx, ny)
+            && other_ny.is_some_and(|other_ny| {
+                other_ny < other.len() && has_many_siblings(other, nx, other_ny)
+            })
+    };
+    in_flat_area(darkest) || in_flat_area(brightest)
+}
+
+// Whether the pixel at (x, y) has at least 3 identical neighbours
+fn has_many_siblings<S: Channel>(rows: &Rows<'_, S>, x: usize, y: usize) -> bool {
+    let center = pixel(rows, x, y);
+    let (around, on_edge) = neighbours(rows, x, y);
+    let siblings = around
+        .filter(|&(nx, ny)| pixel(rows, nx, ny) == center)
+        .count();
+    siblings + on_edge as usize > 2
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLACK: [u8; 4] = [0, 0, 0, 255];
+    const GRAY: [u8; 4] = [128, 128, 128, 255];
+    const WHITE: [u8; 4] = [255, 255, 255, 255];
+
+    #[test]
+    fn should_detect_anti_aliased_edge() {
+        // A vertical edge between black and white, smoothed by a gray column
+        let row = [BLACK, BLACK, GRAY, WHITE, WHITE].concat();
+        let pixels = row.repeat(3);
+        let rows = Rows::new(&pixels, (5, 3));
+        for y in 0..3 {
+            assert!(antialiased(&rows, 2, y, &rows, y));
+        }
+        // Pixels inside the flat areas are not anti-aliased
+        assert!(!antialiased(&rows, 0, 1, &rows, 1));
+        assert!(!antialiased(&rows, 3, 1, &rows, 1));
+    }
+
+    #[test]
+    fn should_not_detect_isolated_pixel() {
+        // A gray dot on black has no brighter neighbour
+        let black = [BLACK, BLACK, BLACK].concat();
+        let dot = [BLACK, GRAY, BLACK].concat();
+        let pixels = [&black[..], &dot[..], &black[..]].concat();
+        let rows = Rows::new(&pixels, (3, 3));
+        assert!(!antialiased(&rows, 1, 1, &rows, 1));
+    }
+}
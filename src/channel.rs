@@ -0,0 +1,70 @@
+use image::{DynamicImage, Primitive};
+use std::borrow::Cow;
+use std::hash::Hash;
+
+/// A channel depth the diff can compare and render natively
+pub(crate) trait Channel: Primitive + Hash + Eq + 'static {
+    /// Normalize any color type to raw RGBA pixels of this depth, borrowing
+    /// them when they already are
+    fn to_rgba(png: &DynamicImage) -> Cow<'_, [Self]>;
+
+    /// Scale an 8 bit value, such as a highlight color, to this depth
+    fn from_u8(value: u8) -> Self;
+
+    fn abs_diff(self, other: Self) -> Self;
+
+    fn to_f32(self) -> f32;
+
+    fn from_f32(value: f32) -> Self;
+}
+
+impl Channel for u8 {
+    fn to_rgba(png: &DynamicImage) -> Cow<'_, [u8]> {
+        match png {
+            DynamicImage::ImageRgba8(rgba) => Cow::Borrowed(rgba.as_raw()),
+            _ => Cow::Owned(png.to_rgba8().into_raw()),
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        value
+    }
+
+    fn abs_diff(self, other: Self) -> Self {
+        u8::abs_diff(self, other)
+    }
+
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+
+    fn from_f32(value: f32) -> Self {
+        value as u8
+    }
+}
+
+impl Channel for u16 {
+    fn to_rgba(png: &DynamicImage) -> Cow<'_, [u16]> {
+        match png {
+            DynamicImage::ImageRgba16(rgba) => Cow::Borrowed(rgba.as_raw()),
+            _ => Cow::Owned(png.to_rgba16().into_raw()),
+        }
+    }
+
+    fn from_u8(value: u8) -> Self {
+        // 0xff -> 0xffff
+        value as u16 * 257
+    }
+
+    fn abs_diff(self, other: Self) -> Self {
+        u16::abs_diff(self, other)
+    }
+
+    fn to_f32(self) -> f32 {
+        self as f32
+    }
+
+    fn from_f32(value: f32) -> Self {
+        value as u16
+    }
+}
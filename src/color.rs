@@ -0,0 +1,207 @@
+use crate::channel::Channel;
+use crate::ColorMetric;
+
+// Largest possible YIQ delta between two colors
+const MAX_YIQ_DELTA: f32 = 35215.0;
+
+// Whether two RGBA pixels are equal under the given metric. The per channel
+// tolerance is given in 8 bit units whatever the depth.
+pub(crate) fn pixels_match<S: Channel>(
+    before: &[S],
+    after: &[S],
+    tolerance: u8,
+    metric: ColorMetric,
+) -> bool {
+    match metric {
+        ColorMetric::PerChannel => {
+            let tolerance = S::from_u8(tolerance);
+            before
+                .iter()
+                .zip(after)
+                .all(|(b, a)| b.abs_diff(*a) <= tolerance)
+        }
+        ColorMetric::Yiq { threshold } => {
+            yiq_delta(to_rgb(before), to_rgb(after)) <= MAX_YIQ_DELTA * threshold * threshold
+        }
+        ColorMetric::Ciede2000 { threshold } => {
+            delta_e_2000(to_lab(to_rgb(before)), to_lab(to_rgb(after))) <= threshold
+        }
+    }
+}
+
+// RGB in 8 bit units, blended over a white background like pixelmatch does
+pub(crate) fn to_rgb<S: Channel>(pixel: &[S]) -> [f32; 3] {
+    let max = S::DEFAULT_MAX_VALUE.to_f32();
+    let alpha = pixel[3].to_f32() / max;
+    let blend = |c: S| 255.0 + (c.to_f32() / max * 255.0 - 255.0) * alpha;
+    [blend(pixel[0]), blend(pixel[1]), blend(pixel[2])]
+}
+
+// Y component of YIQ
+pub(crate) fn brightness([r, g, b]: [f32; 3]) -> f32 {
+    r * 0.298_895_3 + g * 0.586_622_5 + b * 0.114_482_2
+}
+
+fn rgb_to_yiq([r, g, b]: [f32; 3]) -> [f32; 3] {
+    [
+        brightness([r, g, b]),
+        r * 0.595_978 - g * 0.274_176_1 - b * 0.321_801_9,
+        r * 0.211_470_2 - g * 0.522_617_2 + b * 0.311_147,
+    ]
+}
+
+// Squared perceptual distance in the YIQ color space, from "Measuring
+// perceived color difference using YIQ NTSC transmission color space in
+// mobile applications" by Y. Kotsarenko and F. Ramos
+pub(crate) fn yiq_delta(before: [f32; 3], after: [f32; 3]) -> f32 {
+    let [y1, i1, q1] = rgb_to_yiq(before);
+    let [y2, i2, q2] = rgb_to_yiq(after);
+    let (y, i, q) = (y1 - y2, i1 - i2, q1 - q2);
+    0.5053 * y * y + 0.299 * i * i + 0.1957 * q * q
+}
+
+// sRGB in 8 bit units to CIELAB under the D65 illuminant
+fn to_lab(rgb: [f32; 3]) -> [f32; 3] {
+    let [r, g, b] = rgb.map(|c| {
+        let c = c / 255.0;
+        if c <= 0.04045 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    });
+    let x = (r * 0.412_456_4 + g * 0.357_576_1 + b * 0.180_437_5) / 0.950_47;
+    let y = r * 0.212_672_9 + g * 0.715_152_2 + b * 0.072_175;
+    let z = (r * 0.019_333_9 + g * 0.119_192 + b * 0.950_304_1) / 1.088_83;
+    let f = |t: f32| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+// CIEDE2000 color difference, following "The CIEDE2000 Color-Difference
+// Formula: Implementation Notes, Supplementary Test Data, and Mathematical
+// Observations" by G. Sharma, W. Wu and E. N. Dalal
+pub(crate) fn delta_e_2000([l1, a1, b1]: [f32; 3], [l2, a2, b2]: [f32; 3]) -> f32 {
+    let c1 = a1.hypot(b1);
+    let c2 = a2.hypot(b2);
+    let c_mean7 = ((c1 + c2) / 2.0).powi(7);
+    let g = 0.5 * (1.0 - (c_mean7 / (c_mean7 + 25f32.powi(7))).sqrt());
+    let a1 = a1 * (1.0 + g);
+    let a2 = a2 * (1.0 + g);
+    let c1 = a1.hypot(b1);
+    let c2 = a2.hypot(b2);
+    let hue = |a: f32, b: f32| {
+        if a == 0.0 && b == 0.0 {
+            0.0
+        } else {
+            b.atan2(a).to_degrees().rem_euclid(360.0)
+        }
+    };
+    let h1 = hue(a1, b1);
+    let h2 = hue(a2, b2);
+
+    let dl = l2 - l1;
+    let dc = c2 - c1;
+    let dh = if c1 * c2 == 0.0 {
+        0.0
+    } else if (h2 - h1).abs() <= 180.0 {
+        h2 - h1
+    } else if h2 - h1 > 180.0 {
+        h2 - h1 - 360.0
+    } else {
+        h2 - h1 + 360.0
+    };
+    let dh = 2.0 * (c1 * c2).sqrt() * (dh / 2.0).to_radians().sin();
+
+    let l_mean = (l1 + l2) / 2.0;
+    let c_mean = (c1 + c2) / 2.0;
+    let h_mean = if c1 * c2 == 0.0 {
+        h1 + h2
+    } else if (h1 - h2).abs() <= 180.0 {
+        (h1 + h2) / 2.0
+    } else if h1 + h2 < 360.0 {
+        (h1 + h2 + 360.0) / 2.0
+    } else {
+        (h1 + h2 - 360.0) / 2.0
+    };
+    let t = 1.0 - 0.17 * (h_mean - 30.0).to_radians().cos()
+        + 0.24 * (2.0 * h_mean).to_radians().cos()
+        + 0.32 * (3.0 * h_mean + 6.0).to_radians().cos()
+        - 0.20 * (4.0 * h_mean - 63.0).to_radians().cos();
+    let d_theta = 30.0 * (-((h_mean - 275.0) / 25.0).powi(2)).exp();
+    let c_mean7 = c_mean.powi(7);
+    let rc = 2.0 * (c_mean7 / (c_mean7 + 25f32.powi(7))).sqrt();
+    let l_offset = (l_mean - 50.0).powi(2);
+    let sl = 1.0 + 0.015 * l_offset / (20.0 + l_offset).sqrt();
+    let sc = 1.0 + 0.045 * c_mean;
+    let sh = 1.0 + 0.015 * c_mean * t;
+    let rt = -(2.0 * d_theta).to_radians().sin() * rc;
+
+    ((dl / sl).powi(2) + (dc / sc).powi(2) + (dh / sh).powi(2) + rt * (dc / sc) * (dh / sh)).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_compute_ciede2000_of_reference_pairs() {
+        // From the supplementary test data of Sharma et al.
+        let pairs = [
+            ([50.0, 2.6772, -79.7751], [50.0, 0.0, -82.7485], 2.0425),
+            ([50.0, 3.1571, -77.2803], [50.0, 0.0, -82.7485], 2.8615),
+            ([50.0, 2.5, 0.0], [50.0, 0.0, -2.5], 4.3065),
+            ([50.0, -1.0, 2.0], [50.0, 0.0, 0.0], 2.3669),
+            (
+                [60.2574, -34.0099, 36.2677],
+                [60.4626, -34.1751, 39.4387],
+                1.2644,
+            ),
+            (
+                [22.7233, 20.0904, -46.694],
+                [23.0331, 14.973, -42.5619],
+                2.0373,
+            ),
+            ([2.0776, 0.0795, -1.135], [0.9033, -0.0636, -0.5514], 0.9082),
+        ];
+        for (before, after, expected) in pairs {
+            let delta = delta_e_2000(before, after);
+            assert!((delta - expected).abs() < 1e-3, "{} != {}", delta, expected);
+        }
+    }
+
+    #[test]
+    fn should_compare_pixels_perceptually() {
+        let yiq = ColorMetric::Yiq { threshold: 0.1 };
+        let ciede2000 = ColorMetric::Ciede2000 { threshold: 2.3 };
+        for metric in [yiq, ciede2000] {
+            assert!(pixels_match(
+                &[200u8, 100, 50, 255],
+                &[201, 101, 49, 255],
+                0,
+                metric
+            ));
+            assert!(!pixels_match(
+                &[200u8, 100, 50, 255],
+                &[50, 100, 200, 255],
+                0,
+                metric
+            ));
+            // Fully transparent pixels look the same whatever their color
+            assert!(pixels_match(
+                &[200u8, 100, 50, 0],
+                &[50, 100, 200, 0],
+                0,
+                metric
+            ));
+        }
+        assert_eq!(0.0, yiq_delta([0.0; 3], [0.0; 3]));
+        assert!(yiq_delta([0.0; 3], [255.0; 3]) < MAX_YIQ_DELTA);
+    }
+}
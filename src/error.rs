@@ -0,0 +1,75 @@
+use image::{ColorType, ImageError};
+use std::fmt;
+use std::io;
+
+/// Everything that can go wrong while diffing two images
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// An input could not be decoded as an image
+    Decode(ImageError),
+    /// An input uses a color type the diff cannot compare. Reserved: every
+    /// color type is converted to RGBA before diffing, so it is not returned
+    /// today
+    UnsupportedColorType(ColorType),
+    /// An input or the diff result has more pixels than allowed
+    SizeLimitExceeded { width: u64, height: u64 },
+    /// The before and after images cannot be aligned with each other
+    DimensionMismatch {
+        before: (u32, u32),
+        after: (u32, u32),
+    },
+    /// The images differ by more rows than the
+    /// [`max_edit_distance`](crate::DiffOptionsBuilder::max_edit_distance)
+    TooDifferent { max_edit_distance: usize },
+    /// Reading an input failed
+    Io(io::Error),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Decode(e) => write!(f, "Unable to decode image: {}", e),
+            Error::UnsupportedColorType(color) => {
+                write!(f, "Unsupported color type: {:?}", color)
+            }
+            Error::SizeLimitExceeded { width, height } => {
+                write!(f, "Image of {}x{} exceeds the size limit", width, height)
+            }
+            Error::DimensionMismatch { before, after } => write!(
+                f,
+                "Before image of {}x{} does not match after image of {}x{}",
+                before.0, before.1, after.0, after.1
+            ),
+            Error::TooDifferent { max_edit_distance } => {
+                write!(f, "Images differ by more than {} rows", max_edit_distance)
+            }
+            Error::Io(e) => write!(f, "I/O error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Decode(e) => Some(e),
+            Error::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<ImageError> for Error {
+    fn from(e: ImageError) -> Self {
+        match e {
+            ImageError::IoError(e) => Error::Io(e),
+            e => Error::Decode(e),
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(e: io::Error) -> Self {
+        Error::Io(e)
+    }
+}
@@ -0,0 +1,164 @@
+use crate::{myers, replace_all, Budget, DiffElement, DiffResult};
+
+/// Hirschberg's divide and conquer LCS in linear space.
+///
+/// `old` is split in half and the matching split point of `new` is found by
+/// running the LCS length recurrence forward over the first half and
+/// backward over the second half, keeping a single row of the table each
+/// time. Runs in O(N·M) time but only ever holds O(M) lengths, so memory no
+/// longer grows with the square of the image height.
+///
+/// The edits of a part are only known once its rows are computed, so with a
+/// limit on the edits, a Myers search bounded by that limit first gives up on
+/// too different sequences in O((N+M)·D) instead.
+pub(crate) fn diff<'a, T, F: Fn(&T, &T) -> bool>(
+    old: &'a [T],
+    new: &'a [T],
+    eq: &F,
+    budget: &Budget,
+    result: &mut Vec<DiffResult<'a, T>>,
+) {
+    let max_edits = budget.edits_left();
+    if max_edits < old.len() + new.len() {
+        let bounded = Budget::max_edits(max_edits);
+        myers::diff(old, new, eq, &bounded, &mut vec![]);
+        if bounded.too_different() {
+            budget.fits_edits(max_edits + 1);
+            replace_all(old, new, result);
+            return;
+        }
+    }
+    conquer(old, new, eq, budget, result);
+}
+
+// Align old and new, halving old at each step
+fn conquer<'a, T, F: Fn(&T, &T) -> bool>(
+    old: &'a [T],
+    new: &'a [T],
+    eq: &F,
+    budget: &Budget,
+    result: &mut Vec<DiffResult<'a, T>>,
+) {
+    if old.is_empty() || new.is_empty() {
+        budget.spend_edits(old.len() + new.len());
+        replace_all(old, new, result);
+    } else if old.len() == 1 {
+        // A single old element is common with its first match in new
+        match new.iter().position(|n| eq(&old[0], n)) {
+            Some(j) => {
+                budget.spend_edits(new.len() - 1);
+                result.extend(
+                    new[..j]
+                        .iter()
+                        .map(|data| DiffResult::Added(DiffElement { data })),
+                );
+                result.push(DiffResult::Common(DiffElement { data: &old[0] }));
+                result.extend(
+                    new[j + 1..]
+                        .iter()
+                        .map(|data| DiffResult::Added(DiffElement { data })),
+                );
+            }
+            None => {
+                budget.spend_edits(new.len() + 1);
+                replace_all(old, new, result);
+            }
+        }
+    } else if budget.exhausted() || !budget.fits_edits(old.len().abs_diff(new.len())) {
+        replace_all(old, new, result);
+    } else {
+        let mid = old.len() / 2;
+        let (split, longest) = split_point(&old[..mid], &old[mid..], new, eq, budget);
+        // Once the rows are complete, the LCS length gives the edits needed
+        if budget.exhausted() || !budget.fits_edits(old.len() + new.len() - 2 * longest) {
+            replace_all(old, new, result);
+        } else {
+            conquer(&old[..mid], &new[..split], eq, budget, result);
+            conquer(&old[mid..], &new[split..], eq, budget, result);
+        }
+    }
+}
+
+// Index of new where an LCS of (upper ++ lower) x new crosses from upper to
+// lower, and the length of that LCS. The two length rows are dropped before
+// the caller recurses.
+fn split_point<T, F: Fn(&T, &T) -> bool>(
+    upper: &[T],
+    lower: &[T],
+    new: &[T],
+    eq: &F,
+    budget: &Budget,
+) -> (usize, usize) {
+    let forward = lcs_row(upper.iter(), new.iter(), eq, budget);
+    let backward = lcs_row(lower.iter().rev(), new.iter().rev(), eq, budget);
+    let new_len = new.len();
+    let mut split = 0;
+    let mut longest = 0;
+    for j in 0..=new_len {
+        let len = forward[j] + backward[new_len - j];
+        if len > longest {
+            longest = len;
+            split = j;
+        }
+    }
+    (split, longest as usize)
+}
+
+// Last row of the LCS length table of old x new, computed one row at a time,
+// or the last row computed before the budget ran out
+fn lcs_row<'a, T, O, N, F>(old: O, new: N, eq: &F, budget: &Budget) -> Vec<u32>
+where
+    T: 'a,
+    O: Iterator<Item = &'a T>,
+    N: Iterator<Item = &'a T> + Clone,
+    F: Fn(&T, &T) -> bool,
+{
+    let new_len = new.clone().count();
+    let mut prev = vec![0; new_len + 1];
+    let mut curr = vec![0; new_len + 1];
+    for o in old {
+        if budget.exhausted() {
+            break;
+        }
+        for (j, n) in new.clone().enumerate() {
+            curr[j + 1] = if eq(o, n) {
+                prev[j] + 1
+            } else {
+                std::cmp::max(prev[j + 1], curr[j])
+            };
+        }
+        std::mem::swap(&mut prev, &mut curr);
+    }
+    prev
+}
+
+#[cfg(test)]
+mod tests {
+    use super::diff;
+    use crate::Budget;
+    use std::cell::Cell;
+
+    #[test]
+    fn should_give_up_past_max_edits_before_computing_rows() {
+        let old = (0..2000).collect::<Vec<u32>>();
+        let new = (2000..4000).collect::<Vec<u32>>();
+        let comparisons = Cell::new(0);
+        let eq = |o: &u32, n: &u32| {
+            comparisons.set(comparisons.get() + 1);
+            o == n
+        };
+        let budget = Budget::max_edits(10);
+        diff(&old, &new, &eq, &budget, &mut vec![]);
+        assert!(budget.too_different());
+        assert!(comparisons.get() < 1000);
+
+        // Within the limit, the rows are computed as usual
+        let mut new = old.clone();
+        new[1000] = 5000;
+        let budget = Budget::max_edits(10);
+        let mut script = vec![];
+        diff(&old, &new, &eq, &budget, &mut script);
+        assert!(!budget.too_different());
+        assert_eq!(2001, script.len());
+    }
+}
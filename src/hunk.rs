@@ -0,0 +1,290 @@
+use crate::DiffResult;
+use serde::Serialize;
+use std::ops::Range;
+
+/// What happened to the rows of a [`Hunk`]
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HunkKind {
+    /// Rows found in both images
+    Common,
+    /// Rows only found in the after image
+    Added,
+    /// Rows only found in the before image
+    Removed,
+    /// Rows of the before image replaced by similar rows of the after image,
+    /// see [`pair_changed_rows`](crate::DiffOptionsBuilder::pair_changed_rows)
+    Changed,
+    /// Rows of the before image found at another place in the after image,
+    /// see [`detect_moves`](crate::DiffOptionsBuilder::detect_moves)
+    Moved,
+}
+
+/// A run of rows with the same fate, or of columns when diffing
+/// [`Direction::Columns`](crate::Direction::Columns).
+///
+/// `before` and `after` are the rows of the hunk in each image. The range of
+/// the image a hunk is missing from is empty, and starts where the rows
+/// would be.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Hunk {
+    pub kind: HunkKind,
+    pub before: Range<u32>,
+    pub after: Range<u32>,
+}
+
+/// Nearby changes grouped with the common rows around them, like the hunks
+/// of `diff -u`
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct UnifiedHunk {
+    /// Rows of the before image the hunk covers
+    pub before: Range<u32>,
+    /// Rows of the after image the hunk covers, moved rows excepted
+    pub after: Range<u32>,
+    /// The hunks it is made of, starting and ending with context rows
+    pub hunks: Vec<Hunk>,
+}
+
+/// Group hunks the way `diff -u` does: every change comes with up to
+/// `context` common rows before and after it, changes separated by at most
+/// `2 * context` common rows are in the same hunk, and longer stretches of
+/// common rows are left out.
+pub fn unified_hunks(hunks: &[Hunk], context: usize) -> Vec<UnifiedHunk> {
+    let mut groups: Vec<Vec<Hunk>> = vec![];
+    let mut current: Option<Vec<Hunk>> = None;
+    for (i, hunk) in hunks.iter().enumerate() {
+        if hunk.kind != HunkKind::Common {
+            current.get_or_insert_with(Vec::new).push(hunk.clone());
+            continue;
+        }
+        let followed = hunks[i + 1..].iter().any(|h| h.kind != HunkKind::Common);
+        let len = hunk.before.len();
+        match current.take() {
+            Some(mut group) if followed && len <= 2 * context => {
+                group.push(hunk.clone());
+                current = Some(group);
+            }
+            previous => {
+                if let Some(mut group) = previous {
+                    let head = common_rows(hunk, 0, len.min(context));
+                    group.extend(head);
+                    groups.push(group);
+                }
+                if followed {
+                    let tail = common_rows(hunk, len - len.min(context), len);
+                    current = Some(tail.into_iter().collect());
+                }
+            }
+        }
+    }
+    groups.extend(current);
+    groups
+        .into_iter()
+        .map(|hunks| {
+            let before = hunks[0].before.start..hunks[hunks.len() - 1].before.end;
+            let mut after = hunks
+                .iter()
+                .filter(|h| h.kind != HunkKind::Moved)
+                .map(|h| h.after.clone());
+            let after = match after.next() {
+                Some(first) => after.fold(first, |a, b| a.start.min(b.start)..a.end.max(b.end)),
+                // Only moved rows, reported where they come from
+                None => hunks[0].after.clone(),
+            };
+            UnifiedHunk {
+                before,
+                after,
+                hunks,
+            }
+        })
+        .collect()
+}
+
+// The common rows from start to end of a common hunk, if any
+fn common_rows(hunk: &Hunk, start: usize, end: usize) -> Option<Hunk> {
+    let (start, end) = (start as u32, end as u32);
+    (start < end).then(|| Hunk {
+        kind: HunkKind::Common,
+        before: hunk.before.start + start..hunk.before.start + end,
+        after: hunk.after.start + start..hunk.after.start + end,
+    })
+}
+
+/// Group an edit script into hunks, in the order of the before image. Moved
+/// elements are reported once, where they were removed from.
+pub(crate) fn hunks<T>(script: &[DiffResult<'_, T>]) -> Vec<Hunk> {
+    let mut hunks: Vec<Hunk> = vec![];
+    let mut o = 0;
+    let mut n = 0;
+    for d in script {
+        let (kind, before, after) = match d {
+            DiffResult::Common(_) => (HunkKind::Common, o..o + 1, n..n + 1),
+            DiffResult::Added(_) => (HunkKind::Added, o..o, n..n + 1),
+            DiffResult::Removed(_) => (HunkKind::Removed, o..o + 1, n..n),
+            DiffResult::Changed(..) => (HunkKind::Changed, o..o + 1, n..n + 1),
+            DiffResult::MovedFrom(_, destination) => {
+                let destination = *destination as u32;
+                (HunkKind::Moved, o..o + 1, destination..destination + 1)
+            }
+            DiffResult::MovedTo(..) => {
+                n += 1;
+                continue;
+            }
+        };
+        o = before.end;
+        n = if kind == HunkKind::Moved {
+            n
+        } else {
+            after.end
+        };
+        match hunks.last_mut() {
+            Some(last)
+                if last.kind == kind
+                    && last.before.end == before.start
+                    && last.after.end == after.start =>
+            {
+                last.before.end = before.end;
+                last.after.end = after.end;
+            }
+            _ => hunks.push(Hunk {
+                kind,
+                before,
+                after,
+            }),
+        }
+    }
+    hunks
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::moves::find_moves;
+    use crate::{lcs_diff, Algorithm, Budget};
+
+    #[test]
+    fn should_group_script_into_hunks() {
+        let old = ["a", "b", "c", "d"];
+        let new = ["a", "x", "y", "c", "d", "z"];
+        let script = lcs_diff(&old, &new, Algorithm::Myers);
+        assert_eq!(
+            vec![
+                Hunk {
+                    kind: HunkKind::Common,
+                    before: 0..1,
+                    after: 0..1
+                },
+                Hunk {
+                    kind: HunkKind::Added,
+                    before: 1..1,
+                    after: 1..3
+                },
+                Hunk {
+                    kind: HunkKind::Removed,
+                    before: 1..2,
+                    after: 3..3
+                },
+                Hunk {
+                    kind: HunkKind::Common,
+                    before: 2..4,
+                    after: 3..5
+                },
+                Hunk {
+                    kind: HunkKind::Added,
+                    before: 4..4,
+                    after: 5..6
+                },
+            ],
+            hunks(&script)
+        );
+    }
+
+    #[test]
+    fn should_report_moves_once() {
+        let old = ["X", "Y", "a", "b", "c"];
+        let new = ["a", "b", "c", "X", "Y"];
+        let script = find_moves(
+            lcs_diff(&old, &new, Algorithm::Myers),
+            |o, n| o == n,
+            2,
+            &Budget::unlimited(),
+        );
+        assert_eq!(
+            vec![
+                Hunk {
+                    kind: HunkKind::Moved,
+                    before: 0..2,
+                    after: 3..5
+                },
+                Hunk {
+                    kind: HunkKind::Common,
+                    before: 2..5,
+                    after: 0..3
+                },
+            ],
+            hunks(&script)
+        );
+    }
+
+    #[test]
+    fn should_group_hunks_with_context() {
+        let hunk = |kind, before, after| Hunk {
+            kind,
+            before,
+            after,
+        };
+        let hunks = [
+            hunk(HunkKind::Common, 0..10, 0..10),
+            hunk(HunkKind::Removed, 10..11, 10..10),
+            hunk(HunkKind::Common, 11..15, 10..14),
+            hunk(HunkKind::Added, 15..15, 14..16),
+            hunk(HunkKind::Common, 15..30, 16..31),
+            hunk(HunkKind::Changed, 30..31, 31..32),
+            hunk(HunkKind::Common, 31..32, 32..33),
+        ];
+        assert_eq!(
+            vec![
+                UnifiedHunk {
+                    before: 8..17,
+                    after: 8..18,
+                    hunks: vec![
+                        hunk(HunkKind::Common, 8..10, 8..10),
+                        hunk(HunkKind::Removed, 10..11, 10..10),
+                        hunk(HunkKind::Common, 11..15, 10..14),
+                        hunk(HunkKind::Added, 15..15, 14..16),
+                        hunk(HunkKind::Common, 15..17, 16..18),
+                    ],
+                },
+                UnifiedHunk {
+                    before: 28..32,
+                    after: 29..33,
+                    hunks: vec![
+                        hunk(HunkKind::Common, 28..30, 29..31),
+                        hunk(HunkKind::Changed, 30..31, 31..32),
+                        hunk(HunkKind::Common, 31..32, 32..33),
+                    ],
+                },
+            ],
+            unified_hunks(&hunks, 2)
+        );
+        // Without context, only the changes are left
+        let unified = unified_hunks(&hunks, 0);
+        assert_eq!(3, unified.len());
+        assert!(unified.iter().all(|u| u.hunks.len() == 1));
+        // Nothing when nothing changed
+        assert!(unified_hunks(&hunks[..1], 2).is_empty());
+    }
+
+    #[test]
+    fn should_serialize_hunk() {
+        let hunk = Hunk {
+            kind: HunkKind::Added,
+            before: 2..2,
+            after: 1..3,
+        };
+        assert_eq!(
+            r#"{"kind":"added","before":{"start":2,"end":2},"after":{"start":1,"end":3}}"#,
+            serde_json::to_string(&hunk).unwrap()
+        );
+    }
+}
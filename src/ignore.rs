@@ -0,0 +1,157 @@
+use crate::DiffOptions;
+use image::GrayAlphaImage;
+use serde::{Deserialize, Serialize};
+use std::str::FromStr;
+
+/// A rectangle of pixels, in the coordinates of each image
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize, Serialize)]
+pub struct Rect {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Rect {
+    pub fn new(x: u32, y: u32, width: u32, height: u32) -> Self {
+        Rect {
+            x,
+            y,
+            width,
+            height,
+        }
+    }
+
+    pub fn contains(&self, x: u32, y: u32) -> bool {
+        x >= self.x && x - self.x < self.width && y >= self.y && y - self.y < self.height
+    }
+
+    /// Whether both rectangles share a pixel
+    pub fn overlaps(&self, other: &Rect) -> bool {
+        self.x < other.right()
+            && other.x < self.right()
+            && self.y < other.bottom()
+            && other.y < self.bottom()
+    }
+
+    /// Smallest rectangle holding both rectangles
+    pub fn union(&self, other: &Rect) -> Rect {
+        let x = self.x.min(other.x);
+        let y = self.y.min(other.y);
+        let right = self.right().max(other.right());
+        let bottom = self.bottom().max(other.bottom());
+        Rect::new(x, y, right - x, bottom - y)
+    }
+
+    // The column right of the rectangle, clamped for rectangles reaching past
+    // the last coordinate
+    fn right(&self) -> u32 {
+        self.x.saturating_add(self.width)
+    }
+
+    // The row below the rectangle, clamped the same way
+    fn bottom(&self) -> u32 {
+        self.y.saturating_add(self.height)
+    }
+
+    /// Same rectangle with x and y swapped
+    pub(crate) fn transposed(&self) -> Rect {
+        Rect::new(self.y, self.x, self.height, self.width)
+    }
+}
+
+impl FromStr for Rect {
+    type Err = String;
+
+    /// Parse `x,y,width,height`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let values = s
+            .split(',')
+            .map(|v| v.trim().parse::<u32>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|_| format!("Invalid rectangle: {}", s))?;
+        match values[..] {
+            [x, y, width, height] => Ok(Rect::new(x, y, width, height)),
+            _ => Err(format!("Invalid rectangle: {}", s)),
+        }
+    }
+}
+
+// Pixels excluded from the diff, the same in both images
+pub(crate) struct IgnoreRegions<'o> {
+    rects: &'o [Rect],
+    mask: Option<&'o GrayAlphaImage>,
+}
+
+impl<'o> IgnoreRegions<'o> {
+    // None when nothing is ignored
+    pub(crate) fn new(options: &'o DiffOptions) -> Option<Self> {
+        if options.ignore_rects.is_empty() && options.ignore_mask.is_none() {
+            return None;
+        }
+        Some(IgnoreRegions {
+            rects: &options.ignore_rects,
+            mask: options.ignore_mask.as_ref(),
+        })
+    }
+
+    pub(crate) fn contains(&self, x: u32, y: u32) -> bool {
+        self.rects.iter().any(|r| r.contains(x, y))
+            || self.mask.is_some_and(|mask| {
+                x < mask.width() && y < mask.height() && mask.get_pixel(x, y)[1] != 0
+            })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use image::LumaA;
+
+    #[test]
+    fn should_parse_rect() {
+        assert_eq!(Ok(Rect::new(1, 2, 30, 40)), "1,2,30,40".parse());
+        assert!("1,2,30".parse::<Rect>().is_err());
+        assert!("1,2,30,-4".parse::<Rect>().is_err());
+    }
+
+    #[test]
+    fn should_unite_rects() {
+        assert_eq!(
+            Rect::new(1, 2, 9, 5),
+            Rect::new(1, 4, 3, 3).union(&Rect::new(5, 2, 5, 1))
+        );
+        assert!(Rect::new(1, 1, 2, 2).overlaps(&Rect::new(2, 2, 2, 2)));
+        assert!(!Rect::new(1, 1, 2, 2).overlaps(&Rect::new(3, 1, 2, 2)));
+    }
+
+    #[test]
+    fn should_clamp_rects_reaching_past_the_last_coordinate() {
+        let edge = Rect::new(u32::MAX - 1, u32::MAX - 1, 10, 10);
+        assert!(edge.overlaps(&Rect::new(u32::MAX - 1, 0, 1, u32::MAX)));
+        assert!(!edge.overlaps(&Rect::new(0, 0, 10, 10)));
+        assert_eq!(
+            Rect::new(0, 0, u32::MAX, u32::MAX),
+            edge.union(&Rect::new(0, 0, 1, 1))
+        );
+        assert!(edge.contains(u32::MAX - 1, u32::MAX - 1));
+    }
+
+    #[test]
+    fn should_contain_rects_and_opaque_mask_pixels() {
+        let mut mask = GrayAlphaImage::new(3, 3);
+        mask.put_pixel(2, 2, LumaA([0, 255]));
+        let options = DiffOptions::builder()
+            .ignore_rect(Rect::new(0, 0, 2, 1))
+            .ignore_mask(&image::DynamicImage::ImageLumaA8(mask))
+            .build();
+        let ignored = IgnoreRegions::new(&options).unwrap();
+        assert!(ignored.contains(0, 0));
+        assert!(ignored.contains(1, 0));
+        assert!(!ignored.contains(2, 0));
+        assert!(!ignored.contains(0, 1));
+        assert!(ignored.contains(2, 2));
+        assert!(!ignored.contains(3, 3));
+        assert!(IgnoreRegions::new(&DiffOptions::default()).is_none());
+    }
+}
@@ -1,268 +1,2341 @@
-use base64::{decode, encode, DecodeError};
+use channel::Channel;
+use ignore::IgnoreRegions;
 use image::io::Reader;
+use image::ColorType;
 use image::DynamicImage;
-use image::DynamicImage::ImageRgba8;
+use image::DynamicImage::{ImageRgba16, ImageRgba8};
 use image::GenericImageView;
 use image::ImageBuffer;
-use image::Rgba;
+use regions::RegionFinder;
+use std::borrow::Cow;
+use std::cell::{Cell, OnceCell};
+use std::collections::HashMap;
+use std::hash::{Hash, Hasher};
 use std::io::Cursor;
-use std::{cmp, vec};
+use std::ops::Range;
+use std::time::Instant;
+use std::{cmp, iter, vec};
+use stream::RowOp;
+
+mod antialias;
+mod channel;
+mod color;
+mod error;
+mod hirschberg;
+mod hunk;
+mod ignore;
+mod moves;
+mod myers;
+mod options;
+mod patience;
+mod positional;
+mod regions;
+pub mod sequence;
+mod stats;
+mod stream;
+
+pub use error::Error;
+pub use hunk::{unified_hunks, Hunk, HunkKind, UnifiedHunk};
+pub use ignore::Rect;
+pub use options::{
+    Algorithm, ColorMetric, DiffOptions, DiffOptionsBuilder, Direction, OutputMode, WidthAlignment,
+};
+pub use regions::ChangedRegion;
+pub use sequence::create_table;
+pub use stats::DiffStats;
+pub use stream::{DiffRow, DiffRows, RowKind};
 
 pub static BLACK: (u8, u8, u8) = (0, 0, 0);
 pub static RED: (u8, u8, u8) = (255, 119, 119);
 pub static GREEN: (u8, u8, u8) = (99, 195, 99);
+pub static GRAY: (u8, u8, u8) = (191, 191, 191);
+pub static YELLOW: (u8, u8, u8) = (255, 196, 0);
+pub static BLUE: (u8, u8, u8) = (86, 156, 255);
 static RATE: f32 = 0.25;
+// Height of the bands standing for collapsed rows
+const SEPARATOR_HEIGHT: usize = 4;
+
+/// The diff image of two images along with the hunks, stats and changed
+/// regions of the diff
+#[derive(Clone, Debug)]
+pub struct DiffReport {
+    pub image: DynamicImage,
+    pub hunks: Vec<Hunk>,
+    pub stats: DiffStats,
+    pub regions: Vec<ChangedRegion>,
+}
+
+// How far to diff both images: the hunks only need the rows aligned, the
+// stats and regions need every row of the diff image painted but not kept
+#[derive(Clone, Copy, PartialEq)]
+enum Extent {
+    Hunks,
+    Summary,
+    Image,
+}
+
+// The raw RGBA pixels of a diff image, with its width and height, and the
+// hunks, stats and changed regions of the diff. Only what the extent of the
+// diff asked for is filled in, the image is empty otherwise.
+struct RgbaDiff<S> {
+    pixels: Vec<S>,
+    width: u32,
+    height: u32,
+    hunks: Vec<Hunk>,
+    stats: DiffStats,
+    regions: Vec<ChangedRegion>,
+}
 
 #[derive(Debug, PartialEq)]
-enum DiffResult<'a, T: PartialEq> {
+enum DiffResult<'a, T> {
     Removed(DiffElement<'a, T>),
     Common(DiffElement<'a, T>),
     Added(DiffElement<'a, T>),
+    /// A removed element paired with the similar added element replacing it
+    Changed(DiffElement<'a, T>, DiffElement<'a, T>),
+    /// A removed element added back at the given index of the new sequence
+    MovedFrom(DiffElement<'a, T>, usize),
+    /// An added element removed from the given index of the old sequence
+    MovedTo(DiffElement<'a, T>, usize),
 }
 
 #[derive(Debug, PartialEq)]
-struct DiffElement<'a, T: PartialEq> {
+struct DiffElement<'a, T> {
     pub data: &'a T,
 }
 
-// Table is like:
-// \ o l d
-// n
-// e
-// w
-pub fn create_table<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Vec<u32>> {
-    let new_len = new.len();
-    let old_len = old.len();
-    let mut table = vec![vec![0; old_len + 1]; new_len + 1];
-    for i in 0..new_len {
-        let i = new_len - i - 1;
-        for j in 0..old_len {
-            let j = old_len - j - 1;
-            // Performance bottle neck - long string comparison
-            table[i][j] = if new[i] == old[j] {
-                table[i + 1][j + 1] + 1
-            } else {
-                cmp::max(table[i + 1][j], table[i][j + 1])
-            }
+fn common_prefix_len<T, F: Fn(&T, &T) -> bool>(old: &[T], new: &[T], eq: &F) -> usize {
+    old.iter().zip(new).take_while(|p| eq(p.0, p.1)).count()
+}
+
+fn common_suffix_len<T, F: Fn(&T, &T) -> bool>(old: &[T], new: &[T], eq: &F) -> usize {
+    old.iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|p| eq(p.0, p.1))
+        .count()
+}
+
+#[cfg(test)]
+fn lcs_diff<'a, T: Hash + Eq>(
+    old: &'a [T],
+    new: &'a [T],
+    algorithm: Algorithm,
+) -> Vec<DiffResult<'a, T>> {
+    lcs_diff_by(
+        old,
+        new,
+        algorithm,
+        &|o: &T, n: &T| o == n,
+        &Budget::unlimited(),
+    )
+}
+
+// Same as lcs_diff, with a custom equality used to match elements. Exact
+// matches must always be equal under eq. Every comparison is taken from the
+// budget, and the script is only minimal if the budget was not exceeded.
+fn lcs_diff_by<'a, T: Hash + Eq, F: Fn(&T, &T) -> bool>(
+    old: &'a [T],
+    new: &'a [T],
+    algorithm: Algorithm,
+    eq: &F,
+    budget: &Budget,
+) -> Vec<DiffResult<'a, T>> {
+    let eq = &|o: &T, n: &T| {
+        budget.spend();
+        eq(o, n)
+    };
+    let mut result = Vec::with_capacity(cmp::max(old.len(), new.len()));
+    trim_common(
+        old,
+        new,
+        eq,
+        &mut result,
+        |old, new, result| match algorithm {
+            Algorithm::Myers => myers::diff(old, new, eq, budget, result),
+            Algorithm::Hirschberg => hirschberg::diff(old, new, eq, budget, result),
+            Algorithm::Patience => patience::diff(old, new, eq, budget, result),
+        },
+    );
+    result
+}
+
+// Push the common prefix of both sequences, align the elements left between
+// it and the common suffix with align, then push the common suffix
+fn trim_common<'a, T, F, A>(
+    old: &'a [T],
+    new: &'a [T],
+    eq: &F,
+    result: &mut Vec<DiffResult<'a, T>>,
+    align: A,
+) where
+    F: Fn(&T, &T) -> bool,
+    A: FnOnce(&'a [T], &'a [T], &mut Vec<DiffResult<'a, T>>),
+{
+    // Restore common prefix
+    let prefix_size = common_prefix_len(old, new, eq);
+    result.extend(
+        old[..prefix_size]
+            .iter()
+            .map(|data| DiffResult::Common(DiffElement { data })),
+    );
+    let old = &old[prefix_size..];
+    let new = &new[prefix_size..];
+
+    let suffix_size = common_suffix_len(old, new, eq);
+    let (old, old_suffix) = old.split_at(old.len() - suffix_size);
+    let new = &new[..new.len() - suffix_size];
+
+    align(old, new, result);
+
+    // Restore common suffix
+    result.extend(
+        old_suffix
+            .iter()
+            .map(|data| DiffResult::Common(DiffElement { data })),
+    );
+}
+
+// The script removing every element of old and adding every element of new,
+// for when nothing better can be found
+fn replace_all<'a, T>(old: &'a [T], new: &'a [T], result: &mut Vec<DiffResult<'a, T>>) {
+    result.extend(
+        new.iter()
+            .map(|data| DiffResult::Added(DiffElement { data })),
+    );
+    result.extend(
+        old.iter()
+            .map(|data| DiffResult::Removed(DiffElement { data })),
+    );
+}
+
+// Work the diff engines may do on one alignment, as a number of comparisons
+// of elements and a deadline, and the most removed and added elements its
+// script may have. Engines check it between steps and, once it is exhausted,
+// replace every part they have left to align as a whole with replace_all.
+// The script is then valid but no longer minimal. Engines take the edits of
+// their script from the budget as they find them, and give up as soon as a
+// part left needs more edits than there are left.
+struct Budget {
+    comparisons: Cell<u64>,
+    deadline: Option<Instant>,
+    edits: Cell<usize>,
+    exceeded: Cell<bool>,
+    too_different: Cell<bool>,
+}
+
+impl Budget {
+    fn new(options: &DiffOptions) -> Self {
+        Budget {
+            comparisons: Cell::new(options.work_budget.unwrap_or(u64::MAX)),
+            deadline: options
+                .time_budget
+                .and_then(|time_budget| Instant::now().checked_add(time_budget)),
+            edits: Cell::new(options.max_edit_distance.unwrap_or(usize::MAX)),
+            exceeded: Cell::new(false),
+            too_different: Cell::new(false),
         }
     }
-    table
-}
 
-fn lcs_diff<'a, T: PartialEq>(old: &'a [T], new: &'a [T]) -> Vec<DiffResult<'a, T>> {
-    let new_len = new.len();
-    let old_len = old.len();
+    fn unlimited() -> Self {
+        Budget::max_edits(usize::MAX)
+    }
 
-    if new_len == 0 {
-        let mut result = Vec::with_capacity(old_len);
-        let mut o = 0;
-        while o < old_len {
-            result.push(DiffResult::Removed(DiffElement { data: &old[o] }));
-            o += 1;
+    fn max_edits(max: usize) -> Self {
+        Budget {
+            comparisons: Cell::new(u64::MAX),
+            deadline: None,
+            edits: Cell::new(max),
+            exceeded: Cell::new(false),
+            too_different: Cell::new(false),
         }
-        return result;
-    } else if old_len == 0 {
-        let mut result = Vec::with_capacity(new_len);
-        let mut n = 0;
-        while n < new_len {
-            result.push(DiffResult::Added(DiffElement { data: &new[n] }));
-            n += 1;
+    }
+
+    fn spend(&self) {
+        self.comparisons
+            .set(self.comparisons.get().saturating_sub(1));
+    }
+
+    // Whether the engines must give up. Once exhausted, the budget stays so.
+    fn exhausted(&self) -> bool {
+        if !self.exceeded.get()
+            && (self.comparisons.get() == 0
+                || self
+                    .deadline
+                    .is_some_and(|deadline| Instant::now() >= deadline))
+        {
+            self.exceeded.set(true);
         }
-        return result;
-    } else {
-        let mut o = 0;
-        let mut n = 0;
-        let common_prefix = old.iter().zip(new).take_while(|p| p.0 == p.1);
-        let prefix_size = common_prefix.count();
-        let common_suffix = old
-            .iter()
-            .rev()
-            .zip(new.iter().rev())
-            .take(cmp::min(old_len, new_len) - prefix_size)
-            .take_while(|p| p.0 == p.1);
-        let suffix_size = common_suffix.count();
-        let table = create_table(
-            &old[prefix_size..(old_len - suffix_size)],
-            &new[prefix_size..(new_len - suffix_size)],
-        );
-        let new_len = new_len - prefix_size - suffix_size;
-        let old_len = old_len - prefix_size - suffix_size;
-        let mut result = Vec::with_capacity(prefix_size + cmp::max(old_len, new_len) + suffix_size);
+        self.exceeded.get()
+    }
 
-        // Restore common prefix
-        let mut prefix_index = 0;
-        while prefix_index < prefix_size {
-            result.push(DiffResult::Common(DiffElement {
-                data: &old[prefix_index],
-            }));
-            prefix_index += 1;
+    // Whether the engines gave up, a budget running out once they are done
+    // does not count
+    fn exceeded(&self) -> bool {
+        self.exceeded.get()
+    }
+
+    // Whether a part of the script needing at least edits removed and added
+    // elements fits in the edits left. Once it does not, the budget is
+    // exhausted.
+    fn fits_edits(&self, edits: usize) -> bool {
+        if edits > self.edits.get() {
+            self.too_different.set(true);
+            self.exceeded.set(true);
         }
+        !self.too_different.get()
+    }
 
+    // Most removed and added elements the rest of the script may have
+    fn edits_left(&self) -> usize {
+        self.edits.get()
+    }
+
+    // Take the removed and added elements of a part of the script
+    fn spend_edits(&self, edits: usize) {
+        if self.fits_edits(edits) {
+            self.edits.set(self.edits.get() - edits);
+        }
+    }
+
+    // Whether the engines gave up because the script needs more edits than
+    // allowed
+    fn too_different(&self) -> bool {
+        self.too_different.get()
+    }
+}
+
+// Pair the removed and added elements of each block between common elements,
+// in order, into Changed elements when similar. Pairing is positional: the
+// n-th removed element is only ever compared to the n-th added one. The diff
+// engines never produce Changed elements themselves.
+fn pair_changed<'a, T, F: Fn(&T, &T) -> bool>(
+    script: Vec<DiffResult<'a, T>>,
+    similar: F,
+) -> Vec<DiffResult<'a, T>> {
+    let mut result = Vec::with_capacity(script.len());
+    let mut removed = vec![];
+    let mut added = vec![];
+    let flush = |removed: &mut Vec<DiffElement<'a, T>>,
+                 added: &mut Vec<DiffElement<'a, T>>,
+                 result: &mut Vec<DiffResult<'a, T>>| {
+        let mut removed = removed.drain(..);
+        let mut added = added.drain(..);
         loop {
-            if n >= new_len || o >= old_len {
-                break;
-            }
-            let new_index = n + prefix_size;
-            let old_index = o + prefix_size;
-            if new[new_index] == old[old_index] {
-                result.push(DiffResult::Common(DiffElement {
-                    data: &new[new_index],
-                }));
-                n += 1;
-                o += 1;
-            } else if table[n + 1][o] >= table[n][o + 1] {
-                result.push(DiffResult::Added(DiffElement {
-                    data: &new[new_index],
-                }));
-                n += 1;
-            } else {
-                result.push(DiffResult::Removed(DiffElement {
-                    data: &old[old_index],
-                }));
-                o += 1;
+            match (removed.next(), added.next()) {
+                (None, None) => break,
+                (Some(r), Some(a)) if similar(r.data, a.data) => {
+                    result.push(DiffResult::Changed(r, a))
+                }
+                (r, a) => {
+                    result.extend(r.map(DiffResult::Removed));
+                    result.extend(a.map(DiffResult::Added));
+                }
             }
         }
-        while n < new_len {
-            let new_index = n + prefix_size;
-            result.push(DiffResult::Added(DiffElement {
-                data: &new[new_index],
-            }));
-            n += 1;
-        }
-        while o < old_len {
-            let old_index = o + prefix_size;
-            result.push(DiffResult::Removed(DiffElement {
-                data: &old[old_index],
-            }));
-            o += 1;
+    };
+    for d in script {
+        match d {
+            DiffResult::Removed(r) => removed.push(r),
+            DiffResult::Added(a) => added.push(a),
+            d => {
+                flush(&mut removed, &mut added, &mut result);
+                result.push(d);
+            }
         }
+    }
+    flush(&mut removed, &mut added, &mut result);
+    result
+}
+
+fn blend<S: Channel>(base: &[S], rgb: (u8, u8, u8), rate: f32, out: &mut [S]) {
+    let mix = |c: S, h: u8| S::from_f32(c.to_f32() * (1.0 - rate) + S::from_u8(h).to_f32() * rate);
+    out[0] = mix(base[0], rgb.0);
+    out[1] = mix(base[1], rgb.1);
+    out[2] = mix(base[2], rgb.2);
+    out[3] = base[3];
+}
 
-        // Restore common suffix
-        let mut suffix_index = 0;
-        while suffix_index < suffix_size {
-            let old_index = suffix_index + old_len + prefix_size;
-            result.push(DiffResult::Common(DiffElement {
-                data: &old[old_index],
-            }));
-            suffix_index += 1;
+// Paint row blended with rgb into out, rows are as wide as the result
+fn put_diff_pixels<S: Channel>(out: &mut [S], row: &[S], rgb: (u8, u8, u8), rate: f32) {
+    for (out_pixel, pixel) in out.chunks_exact_mut(4).zip(row.chunks_exact(4)) {
+        blend(pixel, rgb, rate, out_pixel);
+    }
+}
+
+fn opaque<S: Channel>(rgb: (u8, u8, u8)) -> [S; 4] {
+    [
+        S::from_u8(rgb.0),
+        S::from_u8(rgb.1),
+        S::from_u8(rgb.2),
+        S::DEFAULT_MAX_VALUE,
+    ]
+}
+
+fn put_mask_pixels<S: Channel>(out: &mut [S], rgb: (u8, u8, u8)) {
+    let pixel = opaque(rgb);
+    for out_pixel in out.chunks_exact_mut(4) {
+        out_pixel.copy_from_slice(&pixel);
+    }
+}
+
+// The color each pixel of a row is highlighted in, if any
+type Highlights = Vec<Option<(u8, u8, u8)>>;
+
+// Diff the pixels of two rows, returning the highlight of each pixel of the
+// after row and how many pixels both rows have in common. Added pixels are
+// highlighted in the added color, and the pixel where removed pixels were cut
+// out in the removed color, or in the changed color when it is added too. The
+// comparisons are taken from the budget, but the rows are always diffed whole.
+fn horizontal_highlights<S: Channel>(
+    before: &[S],
+    after: &[S],
+    options: &DiffOptions,
+    budget: &Budget,
+) -> (Highlights, usize) {
+    let before = before.chunks_exact(4).collect::<Vec<_>>();
+    let after = after.chunks_exact(4).collect::<Vec<_>>();
+    let eq = |b: &&[S], a: &&[S]| {
+        budget.spend();
+        color::pixels_match(b, a, options.tolerance, options.color_metric)
+    };
+    let script = lcs_diff_by(
+        &before,
+        &after,
+        options.algorithm,
+        &eq,
+        &Budget::unlimited(),
+    );
+    let mut added = Vec::with_capacity(after.len());
+    // Whether pixels were removed right before each pixel of the after row,
+    // those removed at its end being marked on its last pixel
+    let mut cut = vec![false; after.len()];
+    let mut common = 0;
+    for d in script {
+        match d {
+            DiffResult::Added(_) | DiffResult::MovedTo(..) => added.push(true),
+            DiffResult::Common(_) | DiffResult::Changed(..) => {
+                added.push(false);
+                common += 1;
+            }
+            DiffResult::Removed(_) | DiffResult::MovedFrom(..) => {
+                let x = cmp::min(added.len(), after.len().saturating_sub(1));
+                if let Some(cut) = cut.get_mut(x) {
+                    *cut = true;
+                }
+            }
         }
-        result
     }
+    let highlights = added
+        .into_iter()
+        .zip(cut)
+        .map(|highlight| match highlight {
+            (true, true) => Some(options.changed_color),
+            (true, false) => Some(options.added_color),
+            (false, true) => Some(options.removed_color),
+            (false, false) => None,
+        })
+        .collect();
+    (highlights, common)
 }
 
-fn blend(base: Rgba<u8>, rgb: (u8, u8, u8), rate: f32) -> Rgba<u8> {
-    Rgba([
-        (base[0] as f32 * (1.0 - rate) + rgb.0 as f32 * (rate)) as u8,
-        (base[1] as f32 * (1.0 - rate) + rgb.1 as f32 * (rate)) as u8,
-        (base[2] as f32 * (1.0 - rate) + rgb.2 as f32 * (rate)) as u8,
-        base[3],
-    ])
+// Whether both rows have at most max_differing_pixels pixels that do not
+// match, pixels_match being given the x of the pixels and both pixels
+fn rows_match<S: Channel, F: Fn(usize, &[S], &[S]) -> bool>(
+    before: &[S],
+    after: &[S],
+    max_differing_pixels: u32,
+    pixels_match: F,
+) -> bool {
+    let max_differing_pixels = max_differing_pixels as usize;
+    before.len() == after.len()
+        && before
+            .chunks_exact(4)
+            .zip(after.chunks_exact(4))
+            .enumerate()
+            .filter(|(x, (b, a))| !pixels_match(*x, b, a))
+            .take(max_differing_pixels + 1)
+            .count()
+            <= max_differing_pixels
 }
 
-fn put_diff_pixels(
+// A row of either image as seen by the diff engines: equal and hashed by its
+// interned id, with its position kept for the comparisons looking at
+// neighbouring rows
+#[derive(Clone, Copy, Debug)]
+struct Row {
+    id: u32,
     y: usize,
-    img: &mut ImageBuffer<Rgba<u8>, Vec<u8>>,
-    row_width: u32,
-    data: &str,
-    rgb: (u8, u8, u8),
-    rate: f32,
-) -> Result<(), base64::DecodeError> {
-    let row = decode(data)?;
-    for x in 0..img.dimensions().0 {
-        let index = x as usize * 4;
-        let pixel = if row_width > x {
-            Rgba([row[index], row[index + 1], row[index + 2], row[index + 3]])
-        } else {
-            Rgba([0, 0, 0, 0])
+}
+
+impl PartialEq for Row {
+    fn eq(&self, other: &Self) -> bool {
+        self.id == other.id
+    }
+}
+
+impl Eq for Row {}
+
+impl Hash for Row {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.id.hash(state);
+    }
+}
+
+// Number of fuzzy comparisons remembered, as a power of two
+const MATCH_MEMO_BITS: u32 = 20;
+
+// A remembered comparison: the before and after row ids and whether they match
+type MemoSlot = Cell<Option<(u32, u32, bool)>>;
+
+// Row equality used to align both images. The engines compare the same rows
+// many times, Hirschberg every pair of them, while screenshots repeat rows a
+// lot. So fuzzy comparisons are remembered by pair of row ids, in a table
+// allocated on the first of them where each pair has a slot picked by its
+// hash, a pair overwriting the one that had its slot. Comparisons looking at
+// the neighbouring rows for anti-aliasing are only remembered when the rows
+// match without it.
+struct RowMatcher<'r, S> {
+    before_rows: Rows<'r, S>,
+    after_rows: Rows<'r, S>,
+    tolerance: u8,
+    metric: ColorMetric,
+    max_differing_pixels: u32,
+    ignore_anti_aliasing: bool,
+    memo: OnceCell<Vec<MemoSlot>>,
+}
+
+impl<'r, S: Channel> RowMatcher<'r, S> {
+    fn new(before_rows: Rows<'r, S>, after_rows: Rows<'r, S>, options: &DiffOptions) -> Self {
+        RowMatcher {
+            before_rows,
+            after_rows,
+            tolerance: options.tolerance,
+            metric: options.color_metric,
+            max_differing_pixels: options.max_differing_pixels,
+            ignore_anti_aliasing: options.ignore_anti_aliasing,
+            memo: OnceCell::new(),
+        }
+    }
+
+    fn matches(&self, o: &Row, n: &Row) -> bool {
+        if o.id == n.id {
+            return true;
+        }
+        if self.tolerance == 0
+            && self.metric == ColorMetric::PerChannel
+            && self.max_differing_pixels == 0
+            && !self.ignore_anti_aliasing
+        {
+            return false;
+        }
+        let slot = self.memo_slot(o.id, n.id);
+        let similar = match slot.get() {
+            Some((before, after, similar)) if (before, after) == (o.id, n.id) => similar,
+            _ => {
+                let similar = self.similar_without_anti_aliasing(o.y, n.y);
+                slot.set(Some((o.id, n.id, similar)));
+                similar
+            }
         };
-        img.put_pixel(x as u32, y as u32, blend(pixel, rgb, rate));
+        similar
+            || (self.ignore_anti_aliasing && self.similar(o.y, n.y, self.max_differing_pixels))
+    }
+
+    // The slot of the memo remembering the comparison of both row ids
+    fn memo_slot(&self, o: u32, n: u32) -> &MemoSlot {
+        let memo = self
+            .memo
+            .get_or_init(|| vec![Cell::new(None); 1 << MATCH_MEMO_BITS]);
+        let key = (o as u64) << 32 | n as u64;
+        let hash = key.wrapping_mul(0x9e37_79b9_7f4a_7c15) >> (64 - MATCH_MEMO_BITS);
+        &memo[hash as usize]
+    }
+
+    // Whether before row o and after row n match without looking at their
+    // neighbouring rows
+    fn similar_without_anti_aliasing(&self, o: usize, n: usize) -> bool {
+        rows_match(
+            self.before_rows.row(o),
+            self.after_rows.row(n),
+            self.max_differing_pixels,
+            |_, b, a| color::pixels_match(b, a, self.tolerance, self.metric),
+        )
+    }
+
+    // Whether before row o and after row n have at most max_differing_pixels
+    // differing pixels
+    fn similar(&self, o: usize, n: usize, max_differing_pixels: u32) -> bool {
+        rows_match(
+            self.before_rows.row(o),
+            self.after_rows.row(n),
+            max_differing_pixels,
+            |x, _, _| self.pixel_matches(x, o, n),
+        )
+    }
+
+    // Whether the pixel at x of before row o matches the one of after row n
+    fn pixel_matches(&self, x: usize, o: usize, n: usize) -> bool {
+        let (before, after) = (&self.before_rows, &self.after_rows);
+        color::pixels_match(
+            &before.row(o)[x * 4..x * 4 + 4],
+            &after.row(n)[x * 4..x * 4 + 4],
+            self.tolerance,
+            self.metric,
+        ) || (self.ignore_anti_aliasing
+            && (antialias::antialiased(before, x, o, after, n)
+                || antialias::antialiased(after, x, n, before, o)))
+    }
+}
+
+/// Assigns every distinct row a small integer id shared by both images, so
+/// the diff engine compares rows in O(1) instead of byte by byte.
+///
+/// The map is keyed by the row bytes themselves: a hash collision only costs
+/// an extra comparison and can never make two different rows equal.
+struct RowInterner<'a, S> {
+    ids: HashMap<&'a [S], u32>,
+}
+
+impl<'a, S: Channel> RowInterner<'a, S> {
+    fn new() -> Self {
+        RowInterner {
+            ids: HashMap::new(),
+        }
+    }
+
+    fn intern(&mut self, rows: Rows<'a, S>) -> Vec<u32> {
+        (0..rows.len())
+            .map(|y| {
+                let row = rows.row(y);
+                let next_id = self.ids.len() as u32;
+                *self.ids.entry(row).or_insert(next_id)
+            })
+            .collect()
+    }
+}
+
+// The rows of RGBA pixels of an image, zero width images still have empty
+// rows
+#[derive(Clone, Copy)]
+pub(crate) struct Rows<'a, S> {
+    pixels: &'a [S],
+    stride: usize,
+    height: usize,
+}
+
+impl<'a, S> Rows<'a, S> {
+    pub(crate) fn new(pixels: &'a [S], (width, height): (u32, u32)) -> Self {
+        Rows {
+            pixels,
+            stride: width as usize * 4,
+            height: height as usize,
+        }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.height
+    }
+
+    pub(crate) fn row(&self, y: usize) -> &'a [S] {
+        &self.pixels[y * self.stride..(y + 1) * self.stride]
+    }
+}
+
+// Column of the canvas where the first column of an image is placed
+fn alignment_offset(src_width: u32, width: u32, alignment: WidthAlignment) -> u32 {
+    match alignment {
+        WidthAlignment::Left | WidthAlignment::Fail => 0,
+        WidthAlignment::Center => (width - src_width) / 2,
+        WidthAlignment::Right => width - src_width,
+    }
+}
+
+// Fill the ignored pixels of row y of an image, placed on a canvas row at
+// offset, with pixel
+fn fill_ignored<S: Channel>(
+    row: &mut [S],
+    y: u32,
+    (src_width, offset): (u32, u32),
+    ignored: &IgnoreRegions,
+    pixel: &[S; 4],
+) {
+    for x in (0..src_width).filter(|&x| ignored.contains(x, y)) {
+        let start = (x + offset) as usize * 4;
+        row[start..start + 4].copy_from_slice(pixel);
+    }
+}
+
+// Place the rows of an image on a canvas of the given width, borrowing the
+// pixels when they already fill it
+fn align_rows<S: Channel>(
+    pixels: Cow<'_, [S]>,
+    (src_width, height): (u32, u32),
+    width: u32,
+    alignment: WidthAlignment,
+) -> Cow<'_, [S]> {
+    if src_width == width {
+        return pixels;
+    }
+    let offset = alignment_offset(src_width, width, alignment) as usize * 4;
+    let src_stride = src_width as usize * 4;
+    let stride = width as usize * 4;
+    let mut aligned = vec![S::zero(); stride * height as usize];
+    for y in 0..height as usize {
+        aligned[y * stride + offset..y * stride + offset + src_stride]
+            .copy_from_slice(&pixels[y * src_stride..(y + 1) * src_stride]);
+    }
+    Cow::Owned(aligned)
+}
+
+// Refuse inputs that cannot be diffed with the given options
+fn check_inputs(
+    before_dim: (u32, u32),
+    after_dim: (u32, u32),
+    options: &DiffOptions,
+) -> Result<(), Error> {
+    check_size(before_dim.0, before_dim.1 as usize, options.max_pixels)?;
+    check_size(after_dim.0, after_dim.1 as usize, options.max_pixels)?;
+    if options.width_alignment == WidthAlignment::Fail && before_dim.0 != after_dim.0 {
+        return Err(Error::DimensionMismatch {
+            before: before_dim,
+            after: after_dim,
+        });
     }
     Ok(())
 }
 
-pub fn diff(
+// Refuse images over the configured limit, or too large to be allocated
+fn check_size(width: u32, height: usize, max_pixels: Option<u64>) -> Result<(), Error> {
+    let width = width as u64;
+    let height = height as u64;
+    let pixels = width * height;
+    let too_large = height > u32::MAX as u64
+        || pixels
+            .checked_mul(4)
+            .is_none_or(|bytes| bytes > isize::MAX as u64)
+        || max_pixels.is_some_and(|max| pixels > max);
+    if too_large {
+        return Err(Error::SizeLimitExceeded { width, height });
+    }
+    Ok(())
+}
+
+pub fn diff(before_png: &DynamicImage, after_png: &DynamicImage) -> Result<DynamicImage, Error> {
+    diff_with_options(before_png, after_png, &DiffOptions::default())
+}
+
+/// Same as [`diff`], configured by the given options
+pub fn diff_with_options(
     before_png: &DynamicImage,
     after_png: &DynamicImage,
-) -> Result<DynamicImage, DecodeError> {
-    let after_w = after_png.dimensions().0;
-    let before_w = before_png.dimensions().0;
-    let before_encoded_png = before_png
-        .as_bytes()
-        .chunks(before_w as usize * 4)
-        .map(encode)
-        .collect::<Vec<String>>();
-    let after_encoded_png = after_png
-        .as_bytes()
-        .chunks(after_w as usize * 4)
-        .map(encode)
-        .collect::<Vec<String>>();
-
-    let diff_result = lcs_diff(&before_encoded_png, &after_encoded_png);
-
-    let height = diff_result.len() as u32;
-    let width = cmp::max(before_w, after_w) as u32;
-    let mut img = ImageBuffer::new(width, height);
-    for (y, d) in diff_result.iter().enumerate() {
-        match d {
-            DiffResult::Added(ref a) => {
-                put_diff_pixels(y, &mut img, after_w as u32, a.data, GREEN, RATE)?
-            }
-            DiffResult::Removed(ref r) => {
-                put_diff_pixels(y, &mut img, before_w as u32, r.data, RED, RATE)?
+    options: &DiffOptions,
+) -> Result<DynamicImage, Error> {
+    diff_report(before_png, after_png, options).map(|report| report.image)
+}
+
+/// The hunks of rows, or of columns when diffing [`Direction::Columns`],
+/// that the diff of both images is made of
+pub fn diff_hunks(
+    before_png: &DynamicImage,
+    after_png: &DynamicImage,
+    options: &DiffOptions,
+) -> Result<Vec<Hunk>, Error> {
+    diff_extent(before_png, after_png, options, Extent::Hunks).map(|report| report.hunks)
+}
+
+/// The stats of the diff of both images
+pub fn diff_stats(
+    before_png: &DynamicImage,
+    after_png: &DynamicImage,
+    options: &DiffOptions,
+) -> Result<DiffStats, Error> {
+    diff_extent(before_png, after_png, options, Extent::Summary).map(|report| report.stats)
+}
+
+/// The regions of touching highlighted pixels of the diff of both images,
+/// with their rectangles in the diff, before and after images
+pub fn diff_regions(
+    before_png: &DynamicImage,
+    after_png: &DynamicImage,
+    options: &DiffOptions,
+) -> Result<Vec<ChangedRegion>, Error> {
+    diff_extent(before_png, after_png, options, Extent::Summary).map(|report| report.regions)
+}
+
+/// Same as [`diff_with_options`], also returning the hunks of
+/// [`diff_hunks`]
+pub fn diff_with_hunks(
+    before_png: &DynamicImage,
+    after_png: &DynamicImage,
+    options: &DiffOptions,
+) -> Result<(DynamicImage, Vec<Hunk>), Error> {
+    diff_report(before_png, after_png, options).map(|report| (report.image, report.hunks))
+}
+
+/// Same as [`diff_with_options`], also returning the hunks, stats and changed
+/// regions of the diff
+pub fn diff_report(
+    before_png: &DynamicImage,
+    after_png: &DynamicImage,
+    options: &DiffOptions,
+) -> Result<DiffReport, Error> {
+    diff_extent(before_png, after_png, options, Extent::Image)
+}
+
+// Diff both images as far as extent, what it leaves out of the report is
+// empty
+fn diff_extent(
+    before_png: &DynamicImage,
+    after_png: &DynamicImage,
+    options: &DiffOptions,
+    extent: Extent,
+) -> Result<DiffReport, Error> {
+    if options.direction == Direction::Columns {
+        let transposed = options.transposed();
+        let report = diff_extent(
+            &transpose(before_png),
+            &transpose(after_png),
+            &transposed,
+            extent,
+        )?;
+        return Ok(DiffReport {
+            image: transpose(&report.image),
+            hunks: report.hunks,
+            stats: report.stats.transposed(),
+            regions: report
+                .regions
+                .into_iter()
+                .map(ChangedRegion::transposed)
+                .collect(),
+        });
+    }
+    let before_dim = before_png.dimensions();
+    let after_dim = after_png.dimensions();
+    check_inputs(before_dim, after_dim, options)?;
+    // Palette images are already expanded to RGB(A) by the decoder, every
+    // other color type is normalized to RGBA so rows have 4 channels per pixel
+    if options.high_bit_depth && is_16_bit(before_png) && is_16_bit(after_png) {
+        let diff = diff_rgba(
+            u16::to_rgba(before_png),
+            before_dim,
+            u16::to_rgba(after_png),
+            after_dim,
+            options,
+            extent,
+        )?;
+        diff.into_report(|width, height, pixels| {
+            ImageBuffer::from_raw(width, height, pixels).map(ImageRgba16)
+        })
+    } else {
+        let diff = diff_rgba(
+            u8::to_rgba(before_png),
+            before_dim,
+            u8::to_rgba(after_png),
+            after_dim,
+            options,
+            extent,
+        )?;
+        diff.into_report(|width, height, pixels| {
+            ImageBuffer::from_raw(width, height, pixels).map(ImageRgba8)
+        })
+    }
+}
+
+/// The rows of the diff image of both images, painted one at a time as they
+/// are iterated, so a large diff can be written out without holding the whole
+/// image in memory. Rows are RGBA with 8 bits per channel, 16-bit inputs are
+/// compared as 8-bit ones.
+///
+/// The rows are the ones of the image of [`diff_with_options`]. When diffing
+/// [`Direction::Columns`], they are its columns, from left to right.
+pub fn diff_rows<'a>(
+    before_png: &'a DynamicImage,
+    after_png: &'a DynamicImage,
+    options: &'a DiffOptions,
+) -> Result<DiffRows<'a>, Error> {
+    let (before_dim, after_dim) = (before_png.dimensions(), after_png.dimensions());
+    let (before_pixels, before_dim, after_pixels, after_dim, options) =
+        if options.direction == Direction::Columns {
+            let swap = |(width, height)| (height, width);
+            (
+                Cow::Owned(u8::to_rgba(&transpose(before_png)).into_owned()),
+                swap(before_dim),
+                Cow::Owned(u8::to_rgba(&transpose(after_png)).into_owned()),
+                swap(after_dim),
+                Cow::Owned(options.transposed()),
+            )
+        } else {
+            (
+                u8::to_rgba(before_png),
+                before_dim,
+                u8::to_rgba(after_png),
+                after_dim,
+                Cow::Borrowed(options),
+            )
+        };
+    check_inputs(before_dim, after_dim, &options)?;
+    let canvas = Canvas::new(before_pixels, before_dim, after_pixels, after_dim, &options);
+    let Alignment {
+        ops, approximate, ..
+    } = edit_rows(&canvas, &options)?;
+    let ops = match options.context_rows {
+        Some(context) => condense(ops, context),
+        None => ops,
+    };
+    check_size(canvas.width, ops.len(), options.max_pixels)?;
+    Ok(DiffRows::new(canvas, options, ops, approximate))
+}
+
+impl<S> RgbaDiff<S> {
+    // Wrap the pixels into an image, with to_image the ImageBuffer::from_raw
+    // of the channel type
+    fn into_report<F>(self, to_image: F) -> Result<DiffReport, Error>
+    where
+        F: FnOnce(u32, u32, Vec<S>) -> Option<DynamicImage>,
+    {
+        let (width, height) = (self.width, self.height);
+        let image = to_image(width, height, self.pixels).ok_or(Error::SizeLimitExceeded {
+            width: width as u64,
+            height: height as u64,
+        })?;
+        Ok(DiffReport {
+            image,
+            hunks: self.hunks,
+            stats: self.stats,
+            regions: self.regions,
+        })
+    }
+}
+
+// Swap x and y
+fn transpose(png: &DynamicImage) -> DynamicImage {
+    png.rotate90().fliph()
+}
+
+fn is_16_bit(png: &DynamicImage) -> bool {
+    matches!(
+        png.color(),
+        ColorType::L16 | ColorType::La16 | ColorType::Rgb16 | ColorType::Rgba16
+    )
+}
+
+// Both images placed on a canvas as wide as the widest one, with their
+// ignored pixels cleared so they always compare equal
+struct Canvas<'a, S: Clone> {
+    width: u32,
+    before_pixels: Cow<'a, [S]>,
+    before_dim: (u32, u32),
+    // Width of the image and column of the canvas where it starts
+    before_place: (u32, u32),
+    after_pixels: Cow<'a, [S]>,
+    after_dim: (u32, u32),
+    after_place: (u32, u32),
+}
+
+impl<'a, S: Channel> Canvas<'a, S> {
+    fn new(
+        before_pixels: Cow<'a, [S]>,
+        before_dim: (u32, u32),
+        after_pixels: Cow<'a, [S]>,
+        after_dim: (u32, u32),
+        options: &DiffOptions,
+    ) -> Self {
+        let width = cmp::max(before_dim.0, after_dim.0);
+        let alignment = options.width_alignment;
+        let before_place = (
+            before_dim.0,
+            alignment_offset(before_dim.0, width, alignment),
+        );
+        let after_place = (after_dim.0, alignment_offset(after_dim.0, width, alignment));
+        let mut before_pixels = align_rows(before_pixels, before_dim, width, alignment);
+        let mut after_pixels = align_rows(after_pixels, after_dim, width, alignment);
+        if let Some(ignored) = IgnoreRegions::new(options) {
+            let stride = width as usize * 4;
+            let cleared = [S::zero(); 4];
+            for (pixels, (_, height), place) in [
+                (before_pixels.to_mut(), before_dim, before_place),
+                (after_pixels.to_mut(), after_dim, after_place),
+            ] {
+                for y in 0..height as usize {
+                    let row = &mut pixels[y * stride..(y + 1) * stride];
+                    fill_ignored(row, y as u32, place, &ignored, &cleared);
+                }
             }
-            DiffResult::Common(ref c) => put_diff_pixels(y, &mut img, width, c.data, BLACK, 0.0)?,
-        }
-    }
-    Ok(ImageRgba8(img))
-}
-
-pub fn diff_slice(
-    before_slice: &[u8],
-    after_slice: &[u8],
-) -> Result<(Vec<u8>, u32, u32), DecodeError> {
-    let before_png = Reader::new(Cursor::new(before_slice))
-        .with_guessed_format()
-        .expect("Cursor io never fails")
-        .decode()
-        .expect("Unable to decode before_png");
-    let after_png = Reader::new(Cursor::new(after_slice))
-        .with_guessed_format()
-        .expect("Cursor io never fails")
-        .decode()
-        .expect("Unable to decode after_png");
-    diff(&before_png, &after_png).map(|img| {
+        }
+        Canvas {
+            width,
+            before_pixels,
+            before_dim,
+            before_place,
+            after_pixels,
+            after_dim,
+            after_place,
+        }
+    }
+
+    fn rows(&self) -> (Rows<'_, S>, Rows<'_, S>) {
         (
-            img.as_bytes().to_vec(),
-            img.dimensions().0,
-            img.dimensions().1,
+            Rows::new(&self.before_pixels, (self.width, self.before_dim.1)),
+            Rows::new(&self.after_pixels, (self.width, self.after_dim.1)),
         )
+    }
+}
+
+// The rows of the diff image before any is collapsed, the hunks they make,
+// and whether the rows were aligned by the fallback of an exceeded budget
+struct Alignment {
+    ops: Vec<RowOp>,
+    hunks: Vec<Hunk>,
+    approximate: bool,
+}
+
+// Align the rows of both images
+fn edit_rows<S: Channel>(
+    canvas: &Canvas<'_, S>,
+    options: &DiffOptions,
+) -> Result<Alignment, Error> {
+    let width = canvas.width;
+    let (before_rows, after_rows) = canvas.rows();
+    let mut interner = RowInterner::new();
+    let to_rows = |ids: Vec<u32>| {
+        ids.into_iter()
+            .enumerate()
+            .map(|(y, id)| Row { id, y })
+            .collect::<Vec<_>>()
+    };
+    let before_ids = to_rows(interner.intern(before_rows));
+    let after_ids = to_rows(interner.intern(after_rows));
+
+    let matcher = RowMatcher::new(before_rows, after_rows, options);
+    let eq = |o: &Row, n: &Row| matcher.matches(o, n);
+    let budget = Budget::new(options);
+    let mut diff_result = lcs_diff_by(&before_ids, &after_ids, options.algorithm, &eq, &budget);
+    let fallback = budget.exceeded() && !budget.too_different();
+    if fallback {
+        diff_result.clear();
+        positional::diff(&before_ids, &after_ids, &eq, &mut diff_result);
+    }
+    if let Some(max_edit_distance) = options.max_edit_distance {
+        // The fallback script is only known once built
+        let edits = || {
+            diff_result
+                .iter()
+                .filter(|d| !matches!(d, DiffResult::Common(_)))
+                .count()
+        };
+        if budget.too_different() || (fallback && edits() > max_edit_distance) {
+            return Err(Error::TooDifferent { max_edit_distance });
+        }
+    }
+    // Moves and rows diffed horizontally are only looked for while the
+    // budget lasts, so none are past the fallback
+    if let Some(min_moved_rows) = options.min_moved_rows {
+        let eq = |o: &Row, n: &Row| {
+            budget.spend();
+            eq(o, n)
+        };
+        diff_result = moves::find_moves(diff_result, eq, min_moved_rows, &budget);
+    }
+    if let Some(min_similarity) = options.min_similarity {
+        let max_differing_pixels = ((1.0 - min_similarity) * width as f32) as u32;
+        diff_result = pair_changed(diff_result, |o, n| {
+            if options.horizontal_diff {
+                if budget.exhausted() {
+                    return false;
+                }
+                let (_, common) = horizontal_highlights(
+                    before_rows.row(o.y),
+                    after_rows.row(n.y),
+                    options,
+                    &budget,
+                );
+                common as f32 >= min_similarity * width as f32
+            } else {
+                matcher.similar(o.y, n.y, max_differing_pixels)
+            }
+        });
+    }
+    let approximate = budget.exceeded();
+    // Walk both images alongside the edit script to find the source rows
+    let mut next = (0, 0);
+    let ops = diff_result
+        .iter()
+        .map(|d| {
+            let (o, n) = next;
+            let (kind, before, after) = match d {
+                DiffResult::Added(_) => (RowKind::Added, o..o, n..n + 1),
+                DiffResult::MovedTo(..) => (RowKind::MovedTo, o..o, n..n + 1),
+                DiffResult::Removed(_) => (RowKind::Removed, o..o + 1, n..n),
+                DiffResult::MovedFrom(..) => (RowKind::MovedFrom, o..o + 1, n..n),
+                DiffResult::Common(_) => (RowKind::Common, o..o + 1, n..n + 1),
+                DiffResult::Changed(..) => (RowKind::Changed, o..o + 1, n..n + 1),
+            };
+            next = (before.end, after.end);
+            RowOp {
+                kind,
+                before,
+                after,
+            }
+        })
+        .collect();
+    Ok(Alignment {
+        ops,
+        hunks: hunk::hunks(&diff_result),
+        approximate,
+    })
+}
+
+// Paint a row of the diff image into out, returning the runs of its
+// highlighted pixels, ignored pixels excepted
+fn render_row<S: Channel>(
+    out: &mut [S],
+    op: &RowOp,
+    canvas: &Canvas<'_, S>,
+    options: &DiffOptions,
+) -> Vec<Range<u32>> {
+    let width = canvas.width;
+    let (before_rows, after_rows) = canvas.rows();
+    let (o, n) = (op.before.start as usize, op.after.start as usize);
+    // Which pixels of a changed row are highlighted
+    let mut highlighted = None;
+    match (op.kind, options.output_mode) {
+        (RowKind::Collapsed, _) => {
+            put_mask_pixels(out, options.separator_color);
+            return vec![];
+        }
+        (RowKind::Added, OutputMode::Blend) => put_diff_pixels(
+            out,
+            after_rows.row(n),
+            options.added_color,
+            options.blend_rate,
+        ),
+        (RowKind::Removed, OutputMode::Blend) => put_diff_pixels(
+            out,
+            before_rows.row(o),
+            options.removed_color,
+            options.blend_rate,
+        ),
+        (RowKind::Common, OutputMode::Blend) => put_diff_pixels(out, after_rows.row(n), BLACK, 0.0),
+        (RowKind::Added, OutputMode::Mask) => put_mask_pixels(out, options.added_color),
+        (RowKind::Removed, OutputMode::Mask) => put_mask_pixels(out, options.removed_color),
+        (RowKind::MovedFrom, OutputMode::Blend) => put_diff_pixels(
+            out,
+            before_rows.row(o),
+            options.moved_color,
+            options.blend_rate,
+        ),
+        (RowKind::MovedTo, OutputMode::Blend) => put_diff_pixels(
+            out,
+            after_rows.row(n),
+            options.moved_color,
+            options.blend_rate,
+        ),
+        (RowKind::MovedFrom | RowKind::MovedTo, OutputMode::Mask) => {
+            put_mask_pixels(out, options.moved_color)
+        }
+        // Left transparent
+        (RowKind::Common, OutputMode::Mask) => {}
+        (RowKind::Changed, output_mode) => {
+            // Highlight of each pixel of the after row
+            let highlights = if options.horizontal_diff {
+                let (highlights, _) = horizontal_highlights(
+                    before_rows.row(o),
+                    after_rows.row(n),
+                    options,
+                    &Budget::unlimited(),
+                );
+                highlights
+            } else {
+                let matcher = RowMatcher::new(before_rows, after_rows, options);
+                (0..width as usize)
+                    .map(|x| (!matcher.pixel_matches(x, o, n)).then_some(options.changed_color))
+                    .collect()
+            };
+            highlighted = Some(highlights.iter().map(Option::is_some).collect::<Vec<_>>());
+            for ((out_pixel, pixel), highlight) in out
+                .chunks_exact_mut(4)
+                .zip(after_rows.row(n).chunks_exact(4))
+                .zip(highlights)
+            {
+                match (highlight, output_mode) {
+                    (None, OutputMode::Blend) => out_pixel.copy_from_slice(pixel),
+                    (None, OutputMode::Mask) => {}
+                    (Some(rgb), OutputMode::Blend) => {
+                        blend(pixel, rgb, options.blend_rate, out_pixel)
+                    }
+                    (Some(rgb), OutputMode::Mask) => out_pixel.copy_from_slice(&opaque(rgb)),
+                }
+            }
+        }
+    }
+    let (src_y, place) = match op.kind {
+        RowKind::Removed | RowKind::MovedFrom => (o as u32, canvas.before_place),
+        _ => (n as u32, canvas.after_place),
+    };
+    let ignored = IgnoreRegions::new(options);
+    if let Some(ignored) = &ignored {
+        fill_ignored(out, src_y, place, ignored, &opaque(options.ignored_color));
+    }
+    if op.kind == RowKind::Common {
+        return vec![];
+    }
+    let (src_width, offset) = place;
+    let is_ignored = |x: u32| {
+        ignored.as_ref().is_some_and(|ignored| {
+            x.checked_sub(offset)
+                .is_some_and(|x| x < src_width && ignored.contains(x, src_y))
+        })
+    };
+    // Rows of a single image only cover its own columns of the canvas
+    let columns = if highlighted.is_some() {
+        0..width
+    } else {
+        offset..offset + src_width
+    };
+    let changed = columns.filter(|&x| {
+        highlighted
+            .as_ref()
+            .is_none_or(|highlighted: &Vec<bool>| highlighted[x as usize])
+            && !is_ignored(x)
+    });
+    regions::runs(changed)
+}
+
+// Diff the raw RGBA pixels of both images as far as extent
+fn diff_rgba<S: Channel>(
+    before_pixels: Cow<'_, [S]>,
+    before_dim: (u32, u32),
+    after_pixels: Cow<'_, [S]>,
+    after_dim: (u32, u32),
+    options: &DiffOptions,
+    extent: Extent,
+) -> Result<RgbaDiff<S>, Error> {
+    let canvas = Canvas::new(before_pixels, before_dim, after_pixels, after_dim, options);
+    let width = canvas.width;
+    let Alignment {
+        ops,
+        hunks,
+        approximate,
+    } = edit_rows(&canvas, options)?;
+    let mut stats = DiffStats::new(&hunks);
+    stats.approximate = approximate;
+    if extent == Extent::Hunks {
+        return Ok(RgbaDiff {
+            pixels: vec![],
+            width: 0,
+            height: 0,
+            hunks,
+            stats,
+            regions: vec![],
+        });
+    }
+    let rows = ops.len() as u32;
+    let ops = match options.context_rows {
+        Some(context) => condense(ops, context),
+        None => ops,
+    };
+    let height = ops.len();
+    check_size(width, height, options.max_pixels)?;
+    let stride = width as usize * 4;
+    // Without the image, every row is painted over the same one
+    let kept = extent == Extent::Image;
+    let mut pixels = vec![S::zero(); stride * if kept { height } else { 1 }];
+    let mut regions = RegionFinder::default();
+    for (y, op) in ops.into_iter().enumerate() {
+        let out_y = if kept { y } else { 0 };
+        let out = &mut pixels[out_y * stride..(out_y + 1) * stride];
+        let runs = render_row(out, &op, &canvas, options);
+        stats.add_changed_runs(y as u32, &runs);
+        regions.add_row(runs, op.before, op.after);
+    }
+    stats.finish((width, rows), before_dim == after_dim);
+    let (pixels, width, height) = if kept {
+        (pixels, width, height as u32)
+    } else {
+        (vec![], 0, 0)
+    };
+    Ok(RgbaDiff {
+        pixels,
+        width,
+        height,
+        hunks,
+        stats,
+        regions: regions.regions(canvas.before_place, canvas.after_place),
     })
 }
 
-#[allow(dead_code)]
-fn gen_lcs<'a, T: PartialEq>(table: &Vec<Vec<u32>>, old: &[T], new: &'a [T]) -> Vec<&'a T> {
-    let o_len = old.len();
-    let n_len = new.len();
-    let mut o = 0;
-    let mut n = 0;
-    let mut res = vec![];
-    while o < o_len && n < n_len {
-        if old[o] == new[n] {
-            res.push(&new[n]);
-            o = o + 1;
-            n = n + 1; // Common
-        } else if table[n + 1][o] >= table[n][o + 1] {
-            n += 1; // Add from new
+// Replace every stretch of common rows further than context rows from a
+// change with a band of collapsed rows standing for them. Stretches no taller
+// than the band are kept as they are.
+fn condense(ops: Vec<RowOp>, context: usize) -> Vec<RowOp> {
+    let kept = kept_rows(&ops, context);
+    let mut condensed = vec![];
+    let mut stretch: Vec<RowOp> = vec![];
+    let put_stretch = |condensed: &mut Vec<RowOp>, stretch: &mut Vec<RowOp>| {
+        if stretch.len() <= SEPARATOR_HEIGHT {
+            condensed.append(stretch);
+        } else if let (Some(first), Some(last)) = (stretch.first(), stretch.last()) {
+            let band = RowOp {
+                kind: RowKind::Collapsed,
+                before: first.before.start..last.before.end,
+                after: first.after.start..last.after.end,
+            };
+            condensed.extend(iter::repeat_n(band, SEPARATOR_HEIGHT));
+            stretch.clear();
+        }
+    };
+    for (op, kept) in ops.into_iter().zip(kept) {
+        if kept {
+            put_stretch(&mut condensed, &mut stretch);
+            condensed.push(op);
         } else {
-            o += 1; // Remove from old
+            stretch.push(op);
         }
     }
-    res
+    put_stretch(&mut condensed, &mut stretch);
+    condensed
+}
+
+// Whether each row is a change or within context rows of one
+fn kept_rows(ops: &[RowOp], context: usize) -> Vec<bool> {
+    let mut kept = vec![false; ops.len()];
+    // Rows before it are already kept
+    let mut kept_until = 0;
+    for (i, op) in ops.iter().enumerate() {
+        if op.kind != RowKind::Common {
+            let end = cmp::min(i + context + 1, ops.len());
+            kept[cmp::max(i.saturating_sub(context), kept_until)..end].fill(true);
+            kept_until = end;
+        }
+    }
+    kept
+}
+
+// Decode an image held in memory
+fn decode_slice(slice: &[u8]) -> Result<DynamicImage, Error> {
+    Ok(Reader::new(Cursor::new(slice))
+        .with_guessed_format()?
+        .decode()?)
+}
+
+pub fn diff_slice(before_slice: &[u8], after_slice: &[u8]) -> Result<(Vec<u8>, u32, u32), Error> {
+    let before_png = decode_slice(before_slice)?;
+    let after_png = decode_slice(after_slice)?;
+    diff(&before_png, &after_png).map(|img| {
+        (
+            img.as_bytes().to_vec(),
+            img.dimensions().0,
+            img.dimensions().1,
+        )
+    })
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::sequence::gen_lcs;
+    use base64::{decode, encode};
+    use image::Rgba;
+    use std::time::Duration;
+
+    // Rebuild both inputs from the edit script and count the common elements
+    pub(crate) fn replay<'a, T: PartialEq>(
+        script: &[DiffResult<'a, T>],
+    ) -> (Vec<&'a T>, Vec<&'a T>, u32) {
+        let mut old = vec![];
+        let mut new = vec![];
+        let mut common = 0;
+        for d in script {
+            match d {
+                DiffResult::Removed(r) => old.push(r.data),
+                DiffResult::Added(a) => new.push(a.data),
+                DiffResult::Common(c) => {
+                    old.push(c.data);
+                    new.push(c.data);
+                    common += 1;
+                }
+                DiffResult::Changed(r, a) => {
+                    old.push(r.data);
+                    new.push(a.data);
+                }
+                DiffResult::MovedFrom(r, _) => old.push(r.data),
+                DiffResult::MovedTo(a, _) => new.push(a.data),
+            }
+        }
+        (old, new, common)
+    }
+
+    fn assert_minimal<T: Hash + Eq + std::fmt::Debug>(
+        algorithm: Algorithm,
+        old: &[T],
+        new: &[T],
+    ) {
+        let script = lcs_diff(old, new, algorithm);
+        let (replayed_old, replayed_new, common) = replay(&script);
+        assert_eq!(old.iter().collect::<Vec<_>>(), replayed_old);
+        assert_eq!(new.iter().collect::<Vec<_>>(), replayed_new);
+        assert_eq!(create_table(old, new)[0][0], common);
+    }
+
+    // Pairs of pseudo random sequences shorter than 40 elements, over a small
+    // alphabet so they share plenty of elements
+    fn random_sequences(count: usize) -> impl Iterator<Item = (Vec<u32>, Vec<u32>)> {
+        let mut seed: u32 = 0x2545_f491;
+        let mut next = move || {
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            seed
+        };
+        (0..count).map(move |_| {
+            let old_len = (next() % 40) as usize;
+            let new_len = (next() % 40) as usize;
+            let old = (0..old_len).map(|_| next() % 4).collect::<Vec<_>>();
+            let new = (0..new_len).map(|_| next() % 4).collect::<Vec<_>>();
+            (old, new)
+        })
+    }
+
+    // An opaque color of its own for each index up to 25
+    fn row_color(y: u32) -> Rgba<u8> {
+        Rgba([10 * y as u8, 0, 0, 255])
+    }
+
+    // An image whose row y is filled with the color of row source(y)
+    fn striped(width: u32, height: u32, source: impl Fn(u32) -> u32) -> DynamicImage {
+        DynamicImage::ImageRgba8(ImageBuffer::from_fn(width, height, |_, y| {
+            row_color(source(y))
+        }))
+    }
+
+    // The pixels of a diff image that are not transparent, with their position
+    fn painted(image: &DynamicImage) -> Vec<(u32, u32, Rgba<u8>)> {
+        image
+            .to_rgba8()
+            .enumerate_pixels()
+            .filter(|(_, _, p)| p[3] != 0)
+            .map(|(x, y, p)| (x, y, *p))
+            .collect()
+    }
+
+    // Per channel pixel equality within tolerance
+    fn within(tolerance: u8) -> impl Fn(usize, &[u8], &[u8]) -> bool {
+        move |_, b, a| color::pixels_match(b, a, tolerance, ColorMetric::PerChannel)
+    }
+
+    #[test]
+    fn should_match_rows_within_tolerance() {
+        let pixel = [10u8, 20, 30, 255];
+        assert!(rows_match(&pixel, &[10, 20, 30, 255], 0, within(0)));
+        assert!(!rows_match(&pixel, &[11, 20, 30, 255], 0, within(0)));
+        assert!(rows_match(&pixel, &[12, 18, 30, 255], 0, within(2)));
+        assert!(!rows_match(&pixel, &[13, 20, 30, 255], 0, within(2)));
+        assert!(!rows_match(
+            &pixel,
+            &[10, 20, 30, 255, 0, 0, 0, 0],
+            1,
+            within(2)
+        ));
+    }
+
+    #[test]
+    fn should_match_rows_with_few_differing_pixels() {
+        let row = [10u8, 20, 30, 255, 10, 20, 30, 255, 10, 20, 30, 255];
+        let one_off = [10u8, 20, 90, 255, 10, 20, 30, 255, 10, 20, 30, 255];
+        let two_off = [10u8, 20, 90, 255, 10, 20, 30, 255, 0, 20, 30, 255];
+        assert!(!rows_match(&row, &one_off, 0, within(0)));
+        assert!(rows_match(&row, &one_off, 1, within(0)));
+        assert!(!rows_match(&row, &two_off, 1, within(0)));
+        assert!(rows_match(&row, &two_off, 2, within(0)));
+        // Pixels within tolerance are not counted
+        assert!(rows_match(&row, &two_off, 1, within(10)));
+    }
+
+    #[test]
+    fn should_remember_fuzzy_row_comparisons_by_ids() {
+        let before = [10u8, 20, 30, 255, 10, 20, 30, 255];
+        let after = [12u8, 20, 30, 255, 10, 20, 30, 255, 90, 20, 30, 255];
+        let (before, after) = (Rows::new(&before, (1, 2)), Rows::new(&after, (1, 3)));
+        let row = |id, y| Row { id, y };
+        // Exact comparisons need no memo
+        let matcher = RowMatcher::new(before, after, &DiffOptions::default());
+        assert!(!matcher.matches(&row(0, 0), &row(1, 0)));
+        assert!(matcher.memo.get().is_none());
+
+        let options = DiffOptions::builder().tolerance(2).build();
+        let matcher = RowMatcher::new(before, after, &options);
+        assert!(matcher.matches(&row(0, 0), &row(1, 0)));
+        assert!(!matcher.matches(&row(0, 1), &row(2, 2)));
+        assert_eq!(Some((0, 1, true)), matcher.memo_slot(0, 1).get());
+        assert_eq!(Some((0, 2, false)), matcher.memo_slot(0, 2).get());
+        // Rows of the same ids are answered from the memo
+        matcher.memo_slot(0, 2).set(Some((0, 2, true)));
+        assert!(matcher.matches(&row(0, 0), &row(2, 2)));
+    }
+
+    #[test]
+    fn should_diff_with_tolerance() {
+        let before = DynamicImage::ImageRgba8(ImageBuffer::from_fn(2, 3, |_, y| {
+            Rgba([100, 100, y as u8, 255])
+        }));
+        let after = DynamicImage::ImageRgba8(ImageBuffer::from_fn(2, 3, |_, y| {
+            Rgba([101, 99, y as u8, 255])
+        }));
+        // Every row changed
+        assert_eq!(6, diff(&before, &after).unwrap().dimensions().1);
+        let options = DiffOptions::builder().tolerance(1).build();
+        let result = diff_with_options(&before, &after, &options).unwrap();
+        assert_eq!(3, result.dimensions().1);
+        assert_eq!(after.as_bytes(), result.as_bytes());
+    }
+
+    #[test]
+    fn should_diff_with_perceptual_metric() {
+        // Each row off by one shade, except the last one changing hue
+        let before = DynamicImage::ImageRgba8(ImageBuffer::from_fn(2, 3, |_, y| {
+            Rgba([200, 100, 50 + y as u8, 255])
+        }));
+        let after = DynamicImage::ImageRgba8(ImageBuffer::from_fn(2, 3, |_, y| match y {
+            2 => Rgba([50, 100, 200, 255]),
+            _ => Rgba([201, 101, 50 + y as u8, 255]),
+        }));
+        for metric in [
+            ColorMetric::Yiq { threshold: 0.1 },
+            ColorMetric::Ciede2000 { threshold: 2.3 },
+        ] {
+            let options = DiffOptions::builder().color_metric(metric).build();
+            let result = diff_with_options(&before, &after, &options).unwrap();
+            assert_eq!(4, result.dimensions().1);
+        }
+    }
+
+    #[test]
+    fn should_ignore_anti_aliasing() {
+        // A black and white edge smoothed by a gray column, rendered with a
+        // slightly different gray in after
+        let edge = |gray: u8| {
+            DynamicImage::ImageRgba8(ImageBuffer::from_fn(5, 3, |x, _| match x {
+                0 | 1 => Rgba([0, 0, 0, 255]),
+                2 => Rgba([gray, gray, gray, 255]),
+                _ => Rgba([255, 255, 255, 255]),
+            }))
+        };
+        let before = edge(128);
+        let after = edge(100);
+        assert_eq!(6, diff(&before, &after).unwrap().dimensions().1);
+        let options = DiffOptions::builder().ignore_anti_aliasing(true).build();
+        let result = diff_with_options(&before, &after, &options).unwrap();
+        assert_eq!(after.as_bytes(), result.as_bytes());
+        // Flat areas still change
+        let blank = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(5, 3, Rgba([255; 4])));
+        let result = diff_with_options(&before, &blank, &options).unwrap();
+        assert_eq!(6, result.dimensions().1);
+    }
+
+    #[test]
+    fn should_ignore_regions() {
+        // A changing clock in the top right corner
+        let clock = |time: u8| {
+            DynamicImage::ImageRgba8(ImageBuffer::from_fn(3, 2, |x, y| match (x, y) {
+                (2, 0) => Rgba([time, time, time, 255]),
+                _ => Rgba([10, 20, 30, 255]),
+            }))
+        };
+        let before = clock(0);
+        let after = clock(100);
+        assert_eq!(3, diff(&before, &after).unwrap().dimensions().1);
+
+        let gray = [191, 191, 191, 255];
+        let pixel = [10, 20, 30, 255];
+        let expected = [pixel, pixel, gray, pixel, pixel, pixel].concat();
+        let options = DiffOptions::builder()
+            .ignore_rect(Rect::new(2, 0, 5, 1))
+            .build();
+        let result = diff_with_options(&before, &after, &options).unwrap();
+        assert_eq!(expected, result.as_bytes());
+
+        let mut mask = ImageBuffer::new(3, 2);
+        mask.put_pixel(2, 0, Rgba([0, 0, 0, 255]));
+        let options = DiffOptions::builder()
+            .ignore_mask(&DynamicImage::ImageRgba8(mask))
+            .build();
+        let result = diff_with_options(&before, &after, &options).unwrap();
+        assert_eq!(expected, result.as_bytes());
+    }
+
+    #[test]
+    fn should_pair_similar_removed_and_added_elements() {
+        let old = ["a", "b1", "c1", "x", "d"];
+        let new = ["a", "b2", "c2", "y", "d"];
+        let script = pair_changed(lcs_diff(&old, &new, Algorithm::Myers), |o, n| {
+            o[..1] == n[..1]
+        });
+        let (replayed_old, replayed_new, _) = replay(&script);
+        assert_eq!(old.iter().collect::<Vec<_>>(), replayed_old);
+        assert_eq!(new.iter().collect::<Vec<_>>(), replayed_new);
+        assert_eq!(
+            vec![
+                DiffResult::Common(DiffElement { data: &"a" }),
+                DiffResult::Changed(DiffElement { data: &"b1" }, DiffElement { data: &"b2" }),
+                DiffResult::Changed(DiffElement { data: &"c1" }, DiffElement { data: &"c2" }),
+                DiffResult::Removed(DiffElement { data: &"x" }),
+                DiffResult::Added(DiffElement { data: &"y" }),
+                DiffResult::Common(DiffElement { data: &"d" }),
+            ],
+            script
+        );
+    }
+
+    #[test]
+    fn should_highlight_changed_pixels_only() {
+        // A button changing color in the middle row
+        let before =
+            DynamicImage::ImageRgba8(ImageBuffer::from_pixel(4, 3, Rgba([10, 20, 30, 255])));
+        let mut after = before.to_rgba8();
+        after.put_pixel(1, 1, Rgba([200, 0, 0, 255]));
+        let after = DynamicImage::ImageRgba8(after);
+        assert_eq!(4, diff(&before, &after).unwrap().dimensions().1);
+
+        let options = DiffOptions::builder()
+            .pair_changed_rows(0.5)
+            .output_mode(OutputMode::Mask)
+            .build();
+        let result = diff_with_options(&before, &after, &options).unwrap();
+        assert_eq!((4, 3), result.dimensions());
+        assert_eq!(vec![(1, 1, Rgba([255, 196, 0, 255]))], painted(&result));
+
+        // Rows with too many differing pixels stay removed and added
+        let options = DiffOptions::builder().pair_changed_rows(0.9).build();
+        let result = diff_with_options(&before, &after, &options).unwrap();
+        assert_eq!(4, result.dimensions().1);
+    }
+
+    #[test]
+    fn should_diff_changed_rows_horizontally() {
+        // A new item inserted at x = 1 shifts the rest of every row right
+        let colors = [
+            [10, 0, 0, 255],
+            [20, 0, 0, 255],
+            [30, 0, 0, 255],
+            [40, 0, 0, 255],
+        ];
+        let before = DynamicImage::ImageRgba8(ImageBuffer::from_fn(5, 3, |x, _| {
+            Rgba(*colors.get(x as usize).unwrap_or(&[255; 4]))
+        }));
+        let after = DynamicImage::ImageRgba8(ImageBuffer::from_fn(5, 3, |x, _| match x {
+            0 => Rgba(colors[0]),
+            1 => Rgba([0, 0, 200, 255]),
+            _ => Rgba(colors[x as usize - 1]),
+        }));
+        let options = DiffOptions::builder()
+            .pair_changed_rows(0.5)
+            .output_mode(OutputMode::Mask)
+            .build();
+        // Most pixels moved, too different to be paired
+        assert_eq!(
+            6,
+            diff_with_options(&before, &after, &options)
+                .unwrap()
+                .dimensions()
+                .1
+        );
+
+        let options = DiffOptions::builder()
+            .pair_changed_rows(0.5)
+            .horizontal_diff(true)
+            .output_mode(OutputMode::Mask)
+            .build();
+        let result = diff_with_options(&before, &after, &options).unwrap();
+        assert_eq!((5, 3), result.dimensions());
+        // A green vertical strip where the item was inserted, and a red one
+        // where the last pixel was pushed out of the rows
+        let green = Rgba([99, 195, 99, 255]);
+        let red = Rgba([255, 119, 119, 255]);
+        assert_eq!(
+            vec![
+                (1, 0, green),
+                (4, 0, red),
+                (1, 1, green),
+                (4, 1, red),
+                (1, 2, green),
+                (4, 2, red)
+            ],
+            painted(&result)
+        );
+    }
+
+    #[test]
+    fn should_diff_pixels_removed_from_changed_rows_horizontally() {
+        // The item at x = 1 removed shifts the rest of every row left
+        let before_colors = [10, 99, 20, 30, 40, 50];
+        let after_colors = [10, 20, 30, 40, 50, 60];
+        let image = |colors: [u8; 6]| {
+            DynamicImage::ImageRgba8(ImageBuffer::from_fn(6, 3, |x, _| {
+                Rgba([colors[x as usize], 0, 0, 255])
+            }))
+        };
+        let (before, after) = (image(before_colors), image(after_colors));
+        let options = DiffOptions::builder()
+            .pair_changed_rows(0.5)
+            .horizontal_diff(true)
+            .output_mode(OutputMode::Mask)
+            .build();
+        let result = diff_with_options(&before, &after, &options).unwrap();
+        assert_eq!((6, 3), result.dimensions());
+        // A red vertical strip where the item was cut out, and a green one
+        // where the last pixel came in
+        let green = Rgba([99, 195, 99, 255]);
+        let red = Rgba([255, 119, 119, 255]);
+        assert_eq!(
+            vec![
+                (1, 0, red),
+                (5, 0, green),
+                (1, 1, red),
+                (5, 1, green),
+                (1, 2, red),
+                (5, 2, green)
+            ],
+            painted(&result)
+        );
+        let stats = diff_stats(&before, &after, &options).unwrap();
+        assert_eq!(3, stats.changed_rows);
+        assert_eq!(6, stats.changed_pixels);
+        assert_eq!(
+            Some(Rect {
+                x: 1,
+                y: 0,
+                width: 5,
+                height: 3
+            }),
+            stats.bounds
+        );
+    }
+
+    #[test]
+    fn should_diff_columns() {
+        // A column inserted at x = 1
+        let before = DynamicImage::ImageRgba8(ImageBuffer::from_fn(2, 3, |x, y| {
+            Rgba([10 * x as u8, 10 * y as u8, 0, 255])
+        }));
+        let after = DynamicImage::ImageRgba8(ImageBuffer::from_fn(3, 3, |x, y| match x {
+            1 => Rgba([0, 0, 200, 255]),
+            _ => Rgba([10 * (x / 2) as u8, 10 * y as u8, 0, 255]),
+        }));
+        let options = DiffOptions::builder()
+            .direction(Direction::Columns)
+            .output_mode(OutputMode::Mask)
+            .ignore_rect(Rect::new(0, 2, 3, 1))
+            .build();
+        let result = diff_with_options(&before, &after, &options).unwrap();
+        assert_eq!((3, 3), result.dimensions());
+        let rgba = result.to_rgba8();
+        let green = Rgba([99, 195, 99, 255]);
+        let gray = Rgba([191, 191, 191, 255]);
+        let transparent = Rgba([0, 0, 0, 0]);
+        for y in 0..2 {
+            assert_eq!(transparent, *rgba.get_pixel(0, y));
+            assert_eq!(green, *rgba.get_pixel(1, y));
+            assert_eq!(transparent, *rgba.get_pixel(2, y));
+        }
+        // The ignored rectangle keeps its x and y
+        assert_eq!(gray, *rgba.get_pixel(0, 2));
+    }
+
+    #[test]
+    fn should_paint_moved_rows() {
+        // A banner of two rows moving from the top to the bottom
+        let before = striped(1, 6, |y| y);
+        let after = striped(1, 6, |y| (y + 2) % 6);
+        let options = DiffOptions::builder()
+            .detect_moves(2)
+            .output_mode(OutputMode::Mask)
+            .build();
+        let result = diff_with_options(&before, &after, &options).unwrap();
+        let blue = [86, 156, 255, 255];
+        let transparent = [0, 0, 0, 0];
+        assert_eq!(
+            [
+                blue,
+                blue,
+                transparent,
+                transparent,
+                transparent,
+                transparent,
+                blue,
+                blue
+            ]
+            .concat(),
+            result.as_bytes()
+        );
+    }
+
+    #[test]
+    fn should_return_hunks() {
+        // Rows 0 and 1 moved to the bottom, row 3 added
+        let before = striped(1, 5, |y| y);
+        let after = striped(1, 6, |y| [2, 3, 4, 20, 0, 1][y as usize]);
+        let hunk = |kind, before, after| Hunk {
+            kind,
+            before,
+            after,
+        };
+        let expected = vec![
+            hunk(HunkKind::Moved, 0..2, 4..6),
+            hunk(HunkKind::Common, 2..5, 0..3),
+            hunk(HunkKind::Added, 5..5, 3..4),
+        ];
+        let options = DiffOptions::builder().detect_moves(2).build();
+        assert_eq!(expected, diff_hunks(&before, &after, &options).unwrap());
+        // Columns are reported when diffing columns
+        let options = DiffOptions::builder()
+            .detect_moves(2)
+            .direction(Direction::Columns)
+            .build();
+        let (result, hunks) =
+            diff_with_hunks(&transpose(&before), &transpose(&after), &options).unwrap();
+        assert_eq!((8, 1), result.dimensions());
+        assert_eq!(expected, hunks);
+    }
+
+    #[test]
+    fn should_compute_stats() {
+        // A row inserted at y = 1, partly ignored, and a pixel changed in the
+        // last row. The ignored first column is cleared already so ignoring
+        // it does not change how rows match.
+        let pixel = |x: u32, y: u32| match x {
+            0 => Rgba([0, 0, 0, 0]),
+            _ => row_color(y),
+        };
+        let before = DynamicImage::ImageRgba8(ImageBuffer::from_fn(4, 3, pixel));
+        let after = DynamicImage::ImageRgba8(ImageBuffer::from_fn(4, 4, |x, y| match (x, y) {
+            (_, 0) => pixel(x, 0),
+            (_, 1) => pixel(x, 20),
+            (1, 3) => Rgba([0, 0, 200, 255]),
+            (_, y) => pixel(x, y - 1),
+        }));
+        let options = DiffOptions::builder()
+            .pair_changed_rows(0.5)
+            .ignore_rect(Rect::new(0, 1, 1, 1))
+            .build();
+        let stats = diff_stats(&before, &after, &options).unwrap();
+        assert_eq!(
+            DiffStats {
+                added_rows: 1,
+                removed_rows: 0,
+                common_rows: 2,
+                changed_rows: 1,
+                moved_rows: 0,
+                changed_pixels: 4,
+                changed_percentage: 25.0,
+                bounds: Some(Rect::new(1, 1, 3, 3)),
+                identical: false,
+                approximate: false,
+            },
+            stats
+        );
+        // The bounds keep their x and y when diffing columns
+        let options = DiffOptions::builder()
+            .direction(Direction::Columns)
+            .pair_changed_rows(0.5)
+            .ignore_rect(Rect::new(1, 0, 1, 1))
+            .build();
+        let stats = diff_stats(&transpose(&before), &transpose(&after), &options).unwrap();
+        assert_eq!(Some(Rect::new(1, 1, 3, 3)), stats.bounds);
+        assert!(diff_stats(&before, &before, &options).unwrap().identical);
+        // Rows of the narrower image do not count the columns it lacks
+        let options = DiffOptions::builder()
+            .width_alignment(WidthAlignment::Center)
+            .build();
+        let stats = diff_stats(&striped(4, 1, |y| y), &striped(2, 1, |y| y), &options).unwrap();
+        assert_eq!((1, 1), (stats.added_rows, stats.removed_rows));
+        assert_eq!(6, stats.changed_pixels);
+        assert_eq!(Some(Rect::new(0, 0, 4, 2)), stats.bounds);
+    }
+
+    #[test]
+    fn should_find_changed_regions() {
+        // A row inserted at y = 1 and a pixel changed in the last row
+        let before = striped(4, 3, |y| y);
+        let after = DynamicImage::ImageRgba8(ImageBuffer::from_fn(4, 4, |x, y| match (x, y) {
+            (_, 0) => row_color(0),
+            (_, 1) => row_color(20),
+            (3, 3) => Rgba([0, 0, 200, 255]),
+            (_, y) => row_color(y - 1),
+        }));
+        let expected = vec![
+            ChangedRegion {
+                diff: Rect::new(0, 1, 4, 1),
+                before: Rect::new(0, 1, 4, 0),
+                after: Rect::new(0, 1, 4, 1),
+            },
+            ChangedRegion {
+                diff: Rect::new(3, 3, 1, 1),
+                before: Rect::new(3, 2, 1, 1),
+                after: Rect::new(3, 3, 1, 1),
+            },
+        ];
+        let options = DiffOptions::builder().pair_changed_rows(0.5).build();
+        assert_eq!(expected, diff_regions(&before, &after, &options).unwrap());
+        // The rectangles keep their x and y when diffing columns
+        let options = DiffOptions::builder()
+            .direction(Direction::Columns)
+            .pair_changed_rows(0.5)
+            .build();
+        let regions = diff_regions(&transpose(&before), &transpose(&after), &options).unwrap();
+        assert_eq!(
+            expected
+                .into_iter()
+                .map(ChangedRegion::transposed)
+                .collect::<Vec<_>>(),
+            regions
+        );
+    }
+
+    #[test]
+    fn should_condense_to_context_rows() {
+        // Row 10 of 20 removed
+        let before = striped(1, 20, |y| y);
+        let after = striped(1, 19, |y| if y < 10 { y } else { y + 1 });
+        let options = DiffOptions::builder()
+            .context_rows(2)
+            .output_mode(OutputMode::Mask)
+            .build();
+        let report = diff_report(&before, &after, &options).unwrap();
+        let black = [0, 0, 0, 255];
+        let red = [255, 119, 119, 255];
+        let transparent = [0, 0, 0, 0];
+        let mut expected = vec![black; 4];
+        expected.extend([transparent, transparent, red, transparent, transparent]);
+        expected.extend([black; 4]);
+        assert_eq!(expected.concat(), report.image.as_bytes());
+        assert_eq!(
+            vec![ChangedRegion {
+                diff: Rect::new(0, 6, 1, 1),
+                before: Rect::new(0, 10, 1, 1),
+                after: Rect::new(0, 10, 1, 0),
+            }],
+            report.regions
+        );
+        assert_eq!(5.0, report.stats.changed_percentage);
+        // Enough context keeps every row
+        let options = DiffOptions::builder().context_rows(10).build();
+        let result = diff_with_options(&before, &after, &options).unwrap();
+        assert_eq!(diff(&before, &after).unwrap(), result);
+        // Stretches no taller than the band are kept
+        let options = DiffOptions::builder().context_rows(0).build();
+        let before = striped(1, 7, |y| y);
+        let after = striped(1, 7, |y| if y == 1 || y == 5 { y + 10 } else { y });
+        let rows = diff_rows(&before, &after, &options).unwrap();
+        assert_eq!(9, rows.len());
+        assert!(rows.into_iter().all(|row| row.kind != RowKind::Collapsed));
+    }
+
+    #[test]
+    fn should_group_rows_like_unified_hunks() {
+        // Row 5 removed, a row inserted before row 10 and row 19 removed
+        let before = striped(1, 24, |y| y);
+        let sources = (0..24)
+            .filter(|&y| y != 5 && y != 19)
+            .flat_map(|y| if y == 10 { vec![25, y] } else { vec![y] })
+            .collect::<Vec<_>>();
+        let after = striped(1, 23, |y| sources[y as usize]);
+        let options = DiffOptions::default();
+        let hunks = diff_hunks(&before, &after, &options).unwrap();
+        let ops = diff_rows(&before, &after, &options)
+            .unwrap()
+            .map(|row| RowOp {
+                kind: row.kind,
+                before: row.before,
+                after: row.after,
+            })
+            .collect::<Vec<_>>();
+        for context in 0..6 {
+            // The runs of rows kept in the condensed image
+            let mut runs: Vec<(Range<u32>, Range<u32>)> = vec![];
+            let mut previous_kept = false;
+            for (op, kept) in ops.iter().zip(kept_rows(&ops, context)) {
+                match runs.last_mut() {
+                    Some((before, after)) if kept && previous_kept => {
+                        before.end = op.before.end;
+                        after.end = op.after.end;
+                    }
+                    _ if kept => runs.push((op.before.clone(), op.after.clone())),
+                    _ => {}
+                }
+                previous_kept = kept;
+            }
+            let unified = unified_hunks(&hunks, context)
+                .into_iter()
+                .map(|u| (u.before, u.after))
+                .collect::<Vec<_>>();
+            assert_eq!(unified, runs);
+        }
+    }
+
+    #[test]
+    fn should_stream_rows_of_diff_image() {
+        let before = DynamicImage::ImageRgba8(ImageBuffer::from_fn(3, 12, |x, y| {
+            Rgba([10 * y as u8, x as u8, 0, 255])
+        }));
+        // Row 2 removed, row 8 changed and a narrower image
+        let after = DynamicImage::ImageRgba8(ImageBuffer::from_fn(2, 11, |x, y| {
+            let y = if y < 2 { y } else { y + 1 };
+            Rgba([10 * y as u8, x as u8, (y == 8) as u8 * 200, 255])
+        }));
+        for options in [
+            DiffOptions::default(),
+            DiffOptions::builder().context_rows(3).build(),
+            DiffOptions::builder()
+                .pair_changed_rows(0.5)
+                .width_alignment(WidthAlignment::Center)
+                .build(),
+            DiffOptions::builder().direction(Direction::Columns).build(),
+        ] {
+            let image = diff_with_options(&before, &after, &options).unwrap();
+            let rows = diff_rows(&before, &after, &options).unwrap();
+            let (width, height) = (rows.width(), rows.height());
+            assert_eq!(height as usize, rows.len());
+            let pixels = rows.flat_map(|row| row.pixels).collect::<Vec<_>>();
+            let image = match options.direction {
+                Direction::Rows => image,
+                Direction::Columns => transpose(&image),
+            };
+            assert_eq!((width, height), image.dimensions());
+            assert_eq!(image.to_rgba8().as_raw(), &pixels);
+        }
+    }
+
+    #[test]
+    fn should_tell_what_each_row_shows() {
+        let before = striped(1, 8, |y| y);
+        // Row 1 removed
+        let after = striped(1, 7, |y| if y == 0 { 0 } else { y + 1 });
+        let options = DiffOptions::builder().context_rows(1).build();
+        let rows = diff_rows(&before, &after, &options)
+            .unwrap()
+            .map(|row| (row.kind, row.before, row.after))
+            .collect::<Vec<_>>();
+        let mut expected = vec![
+            (RowKind::Common, 0..1, 0..1),
+            (RowKind::Removed, 1..2, 1..1),
+            (RowKind::Common, 2..3, 1..2),
+        ];
+        expected.extend(vec![(RowKind::Collapsed, 3..8, 2..7); SEPARATOR_HEIGHT]);
+        assert_eq!(expected, rows);
+    }
+
+    #[test]
+    fn should_find_minimal_scripts_with_minimal_algorithms() {
+        let old = ['H', 'e', 'l', 'l', 'o', ' ', 'w', 'o', 'r', 'l', 'd'];
+        let new = ['H', 'a', 'c', 'k', 'y', 'i', 'n', ' ', 'o', 'o', 'z'];
+        for algorithm in [Algorithm::Myers, Algorithm::Hirschberg] {
+            assert_minimal(algorithm, &old, &new);
+            assert_minimal::<u32>(algorithm, &[], &[]);
+            assert_minimal(algorithm, &[1, 2, 3], &[]);
+            assert_minimal(algorithm, &[], &[1, 2, 3]);
+            assert_minimal(algorithm, &[1, 2, 3], &[1, 2, 3]);
+            assert_minimal(algorithm, &[1, 2, 3], &[4, 5, 6, 7]);
+            for (old, new) in random_sequences(200) {
+                assert_minimal(algorithm, &old, &new);
+            }
+        }
+    }
+
+    #[test]
+    fn should_give_up_on_too_different_images() {
+        let before = striped(2, 10, |y| y);
+        // Two rows changed
+        let after = striped(2, 10, |y| if y == 2 || y == 7 { y + 10 } else { y });
+        for algorithm in [Algorithm::Myers, Algorithm::Hirschberg, Algorithm::Patience] {
+            let builder = DiffOptions::builder().algorithm(algorithm);
+            let options = builder.clone().max_edit_distance(3).build();
+            assert!(matches!(
+                diff_with_options(&before, &after, &options),
+                Err(Error::TooDifferent {
+                    max_edit_distance: 3
+                })
+            ));
+            let options = builder.clone().max_edit_distance(4).build();
+            assert_eq!(
+                diff_with_options(&before, &after, &builder.build()).unwrap(),
+                diff_with_options(&before, &after, &options).unwrap()
+            );
+        }
+    }
+
+    #[test]
+    fn should_cap_the_edits_of_every_engine() {
+        let eq = |o: &u32, n: &u32| o == n;
+        for (old, new) in random_sequences(100) {
+            for algorithm in [Algorithm::Myers, Algorithm::Hirschberg, Algorithm::Patience] {
+                let edits = lcs_diff(&old, &new, algorithm)
+                    .iter()
+                    .filter(|d| !matches!(d, DiffResult::Common(_)))
+                    .count();
+                // The cap is on the edits of the script each engine builds
+                let budget = Budget::max_edits(edits);
+                lcs_diff_by(&old, &new, algorithm, &eq, &budget);
+                assert!(!budget.too_different());
+                if edits > 0 {
+                    let budget = Budget::max_edits(edits - 1);
+                    lcs_diff_by(&old, &new, algorithm, &eq, &budget);
+                    assert!(budget.too_different());
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn should_fall_back_to_positional_alignment_past_budget() {
+        let before = striped(2, 12, |y| y);
+        // Row 20 inserted before row 3 and row 8 removed
+        let after = striped(2, 12, |y| match y {
+            3 => 20,
+            4..=8 => y - 1,
+            _ => y,
+        });
+        for algorithm in [Algorithm::Myers, Algorithm::Hirschberg, Algorithm::Patience] {
+            let builder = DiffOptions::builder().algorithm(algorithm);
+            let exact = diff_stats(&before, &after, &builder.clone().work_budget(1000).build());
+            let exact = exact.unwrap();
+            assert!(!exact.approximate);
+            assert_eq!((1, 1), (exact.added_rows, exact.removed_rows));
+            for options in [
+                builder.clone().work_budget(0).build(),
+                builder.clone().time_budget(Duration::ZERO).build(),
+            ] {
+                let stats = diff_stats(&before, &after, &options).unwrap();
+                assert!(stats.approximate);
+                // Rows 3 to 8 are compared with the rows below them
+                assert_eq!(
+                    (6, 6, 6),
+                    (stats.added_rows, stats.removed_rows, stats.common_rows)
+                );
+                assert!(diff_rows(&before, &after, &options).unwrap().approximate());
+                // Identical images need no comparison past the common rows
+                assert!(!diff_stats(&before, &before, &options).unwrap().approximate);
+            }
+            // Nor are moves and rows diffed horizontally looked for past it
+            let options = builder
+                .work_budget(0)
+                .detect_moves(1)
+                .pair_changed_rows(0.0)
+                .horizontal_diff(true)
+                .build();
+            let stats = diff_stats(&before, &after, &options).unwrap();
+            assert!(stats.approximate);
+            assert_eq!((0, 0), (stats.moved_rows, stats.changed_rows));
+        }
+    }
+
+    #[test]
+    fn should_paint_mask() {
+        let before = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(1, 2, Rgba([1, 2, 3, 255])));
+        let after = DynamicImage::ImageRgba8(ImageBuffer::from_fn(1, 2, |_, y| {
+            Rgba([1, 2, 3 + y as u8, 255])
+        }));
+        let options = DiffOptions::builder()
+            .output_mode(OutputMode::Mask)
+            .added_color((0, 0, 255))
+            .build();
+        let result = diff_with_options(&before, &after, &options).unwrap();
+        assert_eq!(
+            vec![0, 0, 0, 0, 0, 0, 255, 255, 255, 119, 119, 255],
+            result.as_bytes()
+        );
+    }
+
+    #[test]
+    fn should_fail_on_undecodable_slice() {
+        let png = std::fs::read("tests/fixtures/home.png").unwrap();
+        assert!(matches!(
+            diff_slice(&png, b"not a png"),
+            Err(Error::Decode(_))
+        ));
+        assert!(matches!(
+            diff_slice(&png[..png.len() / 2], &png),
+            Err(Error::Decode(_))
+        ));
+        let io = std::io::Error::from(std::io::ErrorKind::UnexpectedEof);
+        assert!(matches!(
+            Error::from(image::ImageError::IoError(io)),
+            Error::Io(_)
+        ));
+    }
+
+    #[test]
+    fn should_fail_on_size_limit() {
+        let png = DynamicImage::ImageRgba8(ImageBuffer::new(10, 10));
+        let options = DiffOptions::builder().max_pixels(99).build();
+        assert!(matches!(
+            diff_with_options(&png, &png, &options),
+            Err(Error::SizeLimitExceeded {
+                width: 10,
+                height: 10
+            })
+        ));
+        let options = DiffOptions::builder().max_pixels(100).build();
+        assert!(diff_with_options(&png, &png, &options).is_ok());
+    }
+
+    #[test]
+    fn should_diff_empty_images() {
+        let empty = DynamicImage::ImageRgba8(ImageBuffer::new(0, 0));
+        let png = DynamicImage::ImageRgba8(ImageBuffer::new(0, 3));
+        assert_eq!((0, 3), diff(&empty, &png).unwrap().dimensions());
+        assert_eq!((0, 3), diff(&png, &png).unwrap().dimensions());
+    }
+
+    // Diff an image against a copy with its middle row changed, in the color
+    // type produced by convert
+    fn assert_diff_color_type(convert: fn(&DynamicImage) -> DynamicImage) {
+        let before = DynamicImage::ImageRgba8(ImageBuffer::from_fn(3, 3, |x, y| {
+            Rgba([40 * x as u8, 80 * y as u8, 120, 255])
+        }));
+        let mut after = before.to_rgba8();
+        for x in 0..3 {
+            after.put_pixel(x, 1, Rgba([255, 255, 255, 255]));
+        }
+        let before = convert(&before);
+        let after = convert(&DynamicImage::ImageRgba8(after));
+
+        let result = diff(&before, &before).unwrap();
+        assert_eq!(before.to_rgba8().as_raw(), result.as_bytes());
+
+        let result = diff(&before, &after).unwrap();
+        assert_eq!((3, 4), result.dimensions());
+        let rows = result.as_bytes().chunks(3 * 4).collect::<Vec<_>>();
+        let before_rgba = before.to_rgba8();
+        let after_rgba = after.to_rgba8();
+        assert_eq!(before_rgba.as_raw()[..12], *rows[0]);
+        assert_eq!(after_rgba.as_raw()[24..], *rows[3]);
+        // One row removed and one added, in either order
+        assert_ne!(rows[1], rows[2]);
+    }
+
+    #[test]
+    fn should_diff_every_color_type() {
+        assert_diff_color_type(|png| png.clone());
+        assert_diff_color_type(|png| DynamicImage::ImageRgb8(png.to_rgb8()));
+        assert_diff_color_type(|png| DynamicImage::ImageLuma8(png.to_luma8()));
+        assert_diff_color_type(|png| DynamicImage::ImageLumaA8(png.to_luma_alpha8()));
+        assert_diff_color_type(|png| DynamicImage::ImageRgb16(png.to_rgb16()));
+        assert_diff_color_type(|png| DynamicImage::ImageRgba16(png.to_rgba16()));
+        assert_diff_color_type(|png| DynamicImage::ImageLuma16(png.to_luma16()));
+        assert_diff_color_type(|png| DynamicImage::ImageLumaA16(png.to_luma_alpha16()));
+        assert_diff_color_type(|png| DynamicImage::ImageRgb32F(png.to_rgb32f()));
+        assert_diff_color_type(|png| DynamicImage::ImageRgba32F(png.to_rgba32f()));
+    }
+
+    #[test]
+    fn should_diff_palette_png() {
+        let palette = std::fs::read("tests/fixtures/palette.png").unwrap();
+        let colors = [[255, 0, 0, 255], [0, 255, 0, 255], [0, 0, 255, 255]];
+        let rgba = DynamicImage::ImageRgba8(ImageBuffer::from_fn(2, 3, |x, y| {
+            Rgba(colors[(x + y) as usize % 3])
+        }));
+        let result = diff(&image::load_from_memory(&palette).unwrap(), &rgba).unwrap();
+        assert_eq!(rgba.as_bytes(), result.as_bytes());
+    }
+
+    #[test]
+    fn should_diff_16_bit_at_full_precision() {
+        // Both rows collapse to the same 8 bit value
+        let before = DynamicImage::ImageRgba16(ImageBuffer::from_fn(1, 2, |_, y| {
+            Rgba([1000 + y as u16, 2000, 3000, 65535])
+        }));
+        let after = DynamicImage::ImageRgba16(ImageBuffer::from_pixel(
+            1,
+            2,
+            Rgba([1000, 2000, 3000, 65535]),
+        ));
+        let result = diff(&before, &after).unwrap();
+        assert_eq!(ColorType::Rgba8, result.color());
+        assert_eq!(2, result.dimensions().1);
+
+        let options = DiffOptions::builder().high_bit_depth(true).build();
+        let result = diff_with_options(&before, &after, &options).unwrap();
+        assert_eq!(ColorType::Rgba16, result.color());
+        assert_eq!(3, result.dimensions().1);
+        let rgba = result.to_rgba16();
+        assert_eq!(Rgba([1000, 2000, 3000, 65535]), *rgba.get_pixel(0, 0));
+        // Highlight colors are scaled to 16 bit
+        let mask = DiffOptions::builder()
+            .high_bit_depth(true)
+            .output_mode(OutputMode::Mask)
+            .build();
+        let result = diff_with_options(&before, &after, &mask).unwrap();
+        let added = result.to_rgba16().pixels().copied().find(|p| p[3] != 0);
+        assert!(added.is_some());
+        assert_eq!(65535, added.unwrap()[3]);
+
+        // 8 bit inputs are still diffed at 8 bit
+        let result = diff_with_options(
+            &DynamicImage::ImageRgba8(before.to_rgba8()),
+            &after,
+            &options,
+        )
+        .unwrap();
+        assert_eq!(ColorType::Rgba8, result.color());
+    }
+
+    #[test]
+    fn should_align_narrower_image() {
+        let before = DynamicImage::ImageRgba8(ImageBuffer::from_pixel(1, 2, Rgba([1, 2, 3, 255])));
+        // The before pixel in the middle of transparent padding
+        let mut after = ImageBuffer::new(3, 2);
+        after.put_pixel(1, 0, Rgba([1, 2, 3, 255]));
+        after.put_pixel(1, 1, Rgba([1, 2, 3, 255]));
+        let after = DynamicImage::ImageRgba8(after);
+
+        let options = DiffOptions::builder()
+            .width_alignment(WidthAlignment::Center)
+            .build();
+        let result = diff_with_options(&before, &after, &options).unwrap();
+        assert_eq!(after.as_bytes(), result.as_bytes());
+
+        for (alignment, alphas) in [
+            (WidthAlignment::Left, [255, 0, 0]),
+            (WidthAlignment::Right, [0, 0, 255]),
+        ] {
+            let options = DiffOptions::builder().width_alignment(alignment).build();
+            let result = diff_with_options(&before, &after, &options).unwrap();
+            assert_eq!((3, 4), result.dimensions());
+            // The removed rows keep the placement of the before pixels
+            assert!(result
+                .as_bytes()
+                .chunks(3 * 4)
+                .any(|row| row.chunks(4).map(|p| p[3]).eq(alphas)));
+        }
+    }
+
+    #[test]
+    fn should_fail_on_width_mismatch() {
+        let before = DynamicImage::ImageRgba8(ImageBuffer::new(1, 2));
+        let after = DynamicImage::ImageRgba8(ImageBuffer::new(3, 2));
+        let options = DiffOptions::builder()
+            .width_alignment(WidthAlignment::Fail)
+            .build();
+        assert!(matches!(
+            diff_with_options(&before, &after, &options),
+            Err(Error::DimensionMismatch {
+                before: (1, 2),
+                after: (3, 2)
+            })
+        ));
+        assert!(diff_with_options(&after, &after, &options).is_ok());
+    }
+
+    #[test]
+    fn should_intern_equal_rows_to_the_same_id() {
+        let before = [[1u8, 2, 3, 4], [5, 6, 7, 8], [1, 2, 3, 4]];
+        let after = [[5u8, 6, 7, 8], [9, 9, 9, 9], [1, 2, 3, 4]];
+        let (before, after) = (before.concat(), after.concat());
+        let mut interner = RowInterner::new();
+        assert_eq!(vec![0, 1, 0], interner.intern(Rows::new(&before, (1, 3))));
+        assert_eq!(vec![1, 2, 0], interner.intern(Rows::new(&after, (1, 3))));
+    }
 
     #[test]
     fn should_create_table_with_encode_pixel_array() {
@@ -1,21 +1,30 @@
 use clap::Parser;
 use image::DynamicImage;
-use lcs_png_diff::diff;
+use lcs_png_diff::{
+    diff_report, Algorithm, ColorMetric, DiffOptions, DiffOptionsBuilder, Direction, Rect,
+    WidthAlignment,
+};
 use rusty_pool::ThreadPool;
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fs::create_dir_all;
 use std::fs::File;
 use std::io;
 use std::io::BufReader;
 use std::path::Path;
-use std::time::Instant;
+use std::time::{Duration, Instant};
 
 #[derive(Debug, Deserialize)]
 struct DiffPair {
     before: String,
     after: String,
     result: Option<String>,
+    #[serde(default)]
+    ignore: Vec<Rect>,
+    ignore_mask: Option<String>,
+    hunks: Option<String>,
+    stats: Option<String>,
+    regions: Option<String>,
 }
 
 #[derive(Parser, Debug)]
@@ -33,9 +42,105 @@ struct Args {
     #[clap(short, long)]
     diff_png: Option<String>,
 
+    /// Path to a json file to write the hunks of the diff to. Set per pair
+    /// with the hunks of a batch instead
+    #[clap(long, conflicts_with = "batch-json")]
+    hunks_json: Option<String>,
+
+    /// Path to a json file to write the stats of the diff to. Set per pair
+    /// with the stats of a batch instead
+    #[clap(long, conflicts_with = "batch-json")]
+    stats_json: Option<String>,
+
+    /// Path to a json file to write the rectangles of the changed regions
+    /// to. Set per pair with the regions of a batch instead
+    #[clap(long, conflicts_with = "batch-json")]
+    regions_json: Option<String>,
+
     /// Path to the batch diff json file
     #[clap(short = 'j', long)]
     batch_json: Option<String>,
+
+    /// Row alignment algorithm: myers, hirschberg (linear memory) or patience
+    #[clap(short = 'l', long, default_value = "myers")]
+    algorithm: Algorithm,
+
+    /// Diff rows, or columns for content growing sideways
+    #[clap(short = 'r', long, default_value = "rows")]
+    direction: Direction,
+
+    /// Maximum difference of any channel for two pixels to be considered equal
+    #[clap(short = 't', long, default_value = "0")]
+    tolerance: u8,
+
+    /// Pixel equality: channel (uses the tolerance), yiq or ciede2000,
+    /// optionally with a threshold such as yiq:0.05
+    #[clap(short = 'm', long, default_value = "channel")]
+    color_metric: ColorMetric,
+
+    /// Number of pixels that may differ between two rows still considered equal
+    #[clap(long, default_value = "0")]
+    max_differing_pixels: u32,
+
+    /// Do not count anti-aliased edge pixels as differences
+    #[clap(long)]
+    ignore_anti_aliasing: bool,
+
+    /// Paint blocks of at least this many rows moved elsewhere in blue
+    #[clap(long)]
+    detect_moves: Option<usize>,
+
+    /// Render a removed row and the added row replacing it as one changed
+    /// row when at least this ratio of their pixels match, e.g. 0.5
+    #[clap(short = 'c', long)]
+    pair_changed_rows: Option<f32>,
+
+    /// Diff the pixels of changed rows horizontally, to show content inserted
+    /// sideways as a green vertical strip, and content deleted sideways as a
+    /// red one where it was cut out. Used with --pair-changed-rows
+    #[clap(long)]
+    horizontal_diff: bool,
+
+    /// Only keep the changed rows and this many common rows around them,
+    /// collapsing longer stretches of common rows into a thin black band
+    #[clap(long)]
+    context_rows: Option<usize>,
+
+    /// Fail instead of diffing pngs needing more than this many rows added
+    /// and removed to be aligned
+    #[clap(long)]
+    max_edit_distance: Option<usize>,
+
+    /// Compare rows at the same position instead of aligning them, and stop
+    /// looking for moved and changed rows, once the alignment took this many
+    /// row comparisons
+    #[clap(long)]
+    work_budget: Option<u64>,
+
+    /// Compare rows at the same position instead of aligning them, and stop
+    /// looking for moved and changed rows, once the alignment took this many
+    /// milliseconds
+    #[clap(long)]
+    time_budget: Option<u64>,
+
+    /// Rectangle excluded from the diff, as x,y,width,height. Can be repeated.
+    /// Applies to every pair of a batch, on top of their own ignore
+    #[clap(short = 'i', long, multiple_occurrences = true)]
+    ignore: Vec<Rect>,
+
+    /// Path to a mask png whose non transparent pixels are excluded from the
+    /// diff. Applies to every pair of a batch without an ignore_mask of its own
+    #[clap(long)]
+    ignore_mask: Option<String>,
+
+    /// Keep 16 bit pngs at 16 bit instead of converting them to 8 bit
+    #[clap(long)]
+    high_bit_depth: bool,
+
+    /// Placement of the narrower png when widths differ: left, center, right
+    /// or fail
+    #[clap(short = 'w', long, default_value = "left")]
+    width_alignment: WidthAlignment,
 }
 
 fn main() -> Result<(), Box<dyn Error>> {
@@ -44,6 +149,41 @@ fn main() -> Result<(), Box<dyn Error>> {
     let after_png = args.after_png;
     let diff_png = args.diff_png;
     let batch_json = args.batch_json;
+    let mut builder = DiffOptions::builder()
+        .algorithm(args.algorithm)
+        .direction(args.direction)
+        .tolerance(args.tolerance)
+        .max_differing_pixels(args.max_differing_pixels)
+        .color_metric(args.color_metric)
+        .ignore_anti_aliasing(args.ignore_anti_aliasing)
+        .high_bit_depth(args.high_bit_depth)
+        .width_alignment(args.width_alignment)
+        .horizontal_diff(args.horizontal_diff);
+    if let Some(min_rows) = args.detect_moves {
+        builder = builder.detect_moves(min_rows);
+    }
+    if let Some(min_similarity) = args.pair_changed_rows {
+        builder = builder.pair_changed_rows(min_similarity);
+    }
+    if let Some(context) = args.context_rows {
+        builder = builder.context_rows(context);
+    }
+    if let Some(max_edit_distance) = args.max_edit_distance {
+        builder = builder.max_edit_distance(max_edit_distance);
+    }
+    if let Some(comparisons) = args.work_budget {
+        builder = builder.work_budget(comparisons);
+    }
+    if let Some(millis) = args.time_budget {
+        builder = builder.time_budget(Duration::from_millis(millis));
+    }
+    for rect in args.ignore {
+        builder = builder.ignore_rect(rect);
+    }
+    if let Some(mask) = args.ignore_mask {
+        let png = image::open(&mask).map_err(|e| format!("{}: {}", mask, e))?;
+        builder = builder.ignore_mask(&png);
+    }
 
     let pairs = if let Some(batch) = batch_json {
         let file = File::open(batch)?;
@@ -54,18 +194,26 @@ fn main() -> Result<(), Box<dyn Error>> {
             after: after_png.unwrap(),
             before: before_png.unwrap(),
             result: diff_png,
+            ignore: vec![],
+            ignore_mask: None,
+            hunks: args.hunks_json,
+            stats: args.stats_json,
+            regions: args.regions_json,
         }]
     };
     let pool = ThreadPool::default();
     for pair in pairs {
-        pool.execute(move || generate_diff(pair));
+        let builder = builder.clone();
+        pool.execute(move || generate_diff(pair, builder));
     }
     pool.shutdown_join();
     Ok(())
 }
 
-/// Generate the png diff image from the input pair
-fn generate_diff(pair: DiffPair) {
+/// Generate the png diff image from the input pair, with the ignored regions
+/// of the pair added to the options. The ignore mask of the pair replaces the
+/// one of the options.
+fn generate_diff(pair: DiffPair, mut builder: DiffOptionsBuilder) {
     let timer = Instant::now();
     let result_filename = match pair.result {
         Some(p) => p,
@@ -73,10 +221,41 @@ fn generate_diff(pair: DiffPair) {
     };
     let before = image::open(&pair.before).expect("Unable to parse before png bitmap");
     let after = image::open(&pair.after).expect("Unable to parse after png bitmap");
-    let result_png =
-        diff(&before, &after).expect("Error occurred while processing the diff result");
-    save_png(&result_png, &result_filename);
-    println!("{}: {:?}", result_filename, timer.elapsed());
+    for rect in pair.ignore {
+        builder = builder.ignore_rect(rect);
+    }
+    if let Some(mask) = pair.ignore_mask {
+        match image::open(&mask) {
+            Ok(mask) => builder = builder.ignore_mask(&mask),
+            Err(e) => {
+                eprintln!("{}: {}", mask, e);
+                return;
+            }
+        }
+    }
+    let report = match diff_report(&before, &after, &builder.build()) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("{}: {}", result_filename, e);
+            return;
+        }
+    };
+    save_png(&report.image, &result_filename);
+    if let Some(hunks_filename) = pair.hunks {
+        save_json(report.hunks.as_slice(), &hunks_filename);
+    }
+    if let Some(stats_filename) = pair.stats {
+        save_json(&report.stats, &stats_filename);
+    }
+    if let Some(regions_filename) = pair.regions {
+        save_json(report.regions.as_slice(), &regions_filename);
+    }
+    let approximate = if report.stats.approximate {
+        " (approximate)"
+    } else {
+        ""
+    };
+    println!("{}: {:?}{}", result_filename, timer.elapsed(), approximate);
 }
 
 /// Save the png to a file
@@ -88,6 +267,19 @@ fn save_png(image: &DynamicImage, filename: &str) {
         .expect("Unable to save the diff result bitmap as a png file");
 }
 
+/// Save the value to a json file, reporting failures
+fn save_json<T: Serialize + ?Sized>(value: &T, filename: &str) {
+    if let Some(path) = Path::new(filename).parent() {
+        let _ = mkdirp(path);
+    }
+    let saved = File::create(filename)
+        .map_err(Box::<dyn Error>::from)
+        .and_then(|file| Ok(serde_json::to_writer_pretty(file, value)?));
+    if let Err(e) = saved {
+        eprintln!("{}: {}", filename, e);
+    }
+}
+
 /// Create the whole path if it doesn't exist
 fn mkdirp<P: AsRef<Path>>(p: P) -> io::Result<()> {
     if let Err(e) = create_dir_all(p) {
@@ -122,10 +314,23 @@ fn happy_path() {
         before: "tests/fixtures/backstopjs_pricing.png".to_owned(),
         after: "tests/fixtures/backstopjs_pricing_after.png".to_owned(),
         result: None,
+        ignore: vec![],
+        ignore_mask: None,
+        hunks: None,
+        stats: None,
+        regions: None,
     };
 
-    generate_diff(pair);
+    generate_diff(pair, DiffOptions::builder());
 
     let result = image::open("tests/fixtures/backstopjs_pricing_result.png");
     println!("{:?}", result);
 }
+
+#[test]
+fn should_reject_json_outputs_with_batch() {
+    for output in ["--hunks-json", "--stats-json", "--regions-json"] {
+        let args = ["lcs-png-diff", "-j", "pair.json", output, "out.json"];
+        assert!(Args::try_parse_from(args).is_err());
+    }
+}
@@ -0,0 +1,197 @@
+use crate::{Budget, DiffResult};
+
+// A run of consecutive removed or added elements: the index of its first
+// element in its sequence, and the positions of its elements in the script
+struct Block {
+    start: usize,
+    positions: Vec<usize>,
+}
+
+// Group the removed or added elements, given as (index in their sequence,
+// position in the script), into runs of consecutive indices
+fn blocks(elements: &[(usize, usize)]) -> Vec<Block> {
+    let mut blocks: Vec<Block> = vec![];
+    for &(index, position) in elements {
+        match blocks.last_mut() {
+            Some(block) if block.start + block.positions.len() == index => {
+                block.positions.push(position)
+            }
+            _ => blocks.push(Block {
+                start: index,
+                positions: vec![position],
+            }),
+        }
+    }
+    blocks
+}
+
+/// Find blocks of at least `min_len` removed elements added back elsewhere,
+/// and mark them as moved.
+///
+/// A removed block is moved when it appears whole inside an added block, or
+/// an added block whole inside it. `MovedFrom` elements keep the index of
+/// their destination in `new`, `MovedTo` elements the index of their source
+/// in `old`. Once the budget is exhausted, the blocks left are not compared.
+pub(crate) fn find_moves<'a, T, F: Fn(&T, &T) -> bool>(
+    script: Vec<DiffResult<'a, T>>,
+    eq: F,
+    min_len: usize,
+    budget: &Budget,
+) -> Vec<DiffResult<'a, T>> {
+    let mut removed = vec![];
+    let mut added = vec![];
+    let mut o = 0;
+    let mut n = 0;
+    for (position, d) in script.iter().enumerate() {
+        match d {
+            DiffResult::Removed(_) => {
+                removed.push((o, position));
+                o += 1;
+            }
+            DiffResult::Added(_) => {
+                added.push((n, position));
+                n += 1;
+            }
+            DiffResult::MovedFrom(..) => o += 1,
+            DiffResult::MovedTo(..) => n += 1,
+            DiffResult::Common(_) | DiffResult::Changed(..) => {
+                o += 1;
+                n += 1;
+            }
+        }
+    }
+    let data = |position: usize| match &script[position] {
+        DiffResult::Removed(e) | DiffResult::Added(e) => e.data,
+        _ => unreachable!("only removed and added elements are in blocks"),
+    };
+
+    // Index in the other sequence of each moved element, by script position
+    let mut moved_to: Vec<Option<usize>> = vec![None; script.len()];
+    let added_blocks = blocks(&added);
+    for removed_block in blocks(&removed) {
+        for added_block in &added_blocks {
+            let removed_len = removed_block.positions.len();
+            let added_len = added_block.positions.len();
+            let len = removed_len.min(added_len);
+            if len < min_len || budget.exhausted() {
+                continue;
+            }
+            // Offsets of the shorter block inside the longer one
+            let skips = |k| {
+                if removed_len <= added_len {
+                    (0, k)
+                } else {
+                    (k, 0)
+                }
+            };
+            let found = (0..=removed_len.max(added_len) - len).find(|&k| {
+                let (removed_skip, added_skip) = skips(k);
+                (0..len).all(|i| {
+                    let r = removed_block.positions[removed_skip + i];
+                    let a = added_block.positions[added_skip + i];
+                    moved_to[r].is_none() && moved_to[a].is_none() && eq(data(r), data(a))
+                })
+            });
+            if let Some(k) = found {
+                let (removed_skip, added_skip) = skips(k);
+                for i in 0..len {
+                    let r = removed_block.positions[removed_skip + i];
+                    let a = added_block.positions[added_skip + i];
+                    moved_to[r] = Some(added_block.start + added_skip + i);
+                    moved_to[a] = Some(removed_block.start + removed_skip + i);
+                }
+            }
+        }
+    }
+
+    script
+        .into_iter()
+        .zip(moved_to)
+        .map(|(d, moved_to)| match (d, moved_to) {
+            (DiffResult::Removed(e), Some(destination)) => DiffResult::MovedFrom(e, destination),
+            (DiffResult::Added(e), Some(source)) => DiffResult::MovedTo(e, source),
+            (d, _) => d,
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::replay;
+    use crate::{lcs_diff, Algorithm, DiffOptions};
+
+    #[test]
+    fn should_find_moved_block() {
+        let old = ["a", "X", "Y", "c", "d", "e"];
+        let new = ["a", "c", "d", "e", "X", "Y"];
+        let script = find_moves(
+            lcs_diff(&old, &new, Algorithm::Myers),
+            |o, n| o == n,
+            2,
+            &Budget::unlimited(),
+        );
+        let (replayed_old, replayed_new, _) = replay(&script);
+        assert_eq!(old.iter().collect::<Vec<_>>(), replayed_old);
+        assert_eq!(new.iter().collect::<Vec<_>>(), replayed_new);
+        let moves = script
+            .iter()
+            .filter_map(|d| match d {
+                DiffResult::MovedFrom(e, destination) => Some(("from", *e.data, *destination)),
+                DiffResult::MovedTo(e, source) => Some(("to", *e.data, *source)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            vec![
+                ("from", "X", 4),
+                ("from", "Y", 5),
+                ("to", "X", 1),
+                ("to", "Y", 2)
+            ],
+            moves
+        );
+    }
+
+    #[test]
+    fn should_find_block_moved_inside_larger_change() {
+        let old = ["a", "X", "Y", "b", "c", "d"];
+        let new = ["a", "b", "c", "d", "n", "X", "Y", "m"];
+        let budget = Budget::unlimited();
+        let script = find_moves(
+            lcs_diff(&old, &new, Algorithm::Myers),
+            |o, n| o == n,
+            2,
+            &budget,
+        );
+        assert_eq!(
+            4,
+            script
+                .iter()
+                .filter(|d| matches!(d, DiffResult::MovedFrom(..) | DiffResult::MovedTo(..)))
+                .count()
+        );
+        // Too short to be a move
+        let script = find_moves(
+            lcs_diff(&old, &new, Algorithm::Myers),
+            |o, n| o == n,
+            3,
+            &budget,
+        );
+        assert!(script
+            .iter()
+            .all(|d| !matches!(d, DiffResult::MovedFrom(..) | DiffResult::MovedTo(..))));
+        // Nor looked for past the budget
+        let budget = Budget::new(&DiffOptions::builder().work_budget(0).build());
+        let script = find_moves(
+            lcs_diff(&old, &new, Algorithm::Myers),
+            |o, n| o == n,
+            2,
+            &budget,
+        );
+        assert!(script
+            .iter()
+            .all(|d| !matches!(d, DiffResult::MovedFrom(..) | DiffResult::MovedTo(..))));
+        assert!(budget.exceeded());
+    }
+}
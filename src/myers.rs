@@ -0,0 +1,179 @@
+use crate::{common_prefix_len, common_suffix_len, replace_all, trim_common, Budget, DiffResult};
+use std::cmp;
+use std::ops::{Index, IndexMut};
+
+// Furthest reaching x per diagonal k, indexed from -max_d to max_d
+struct V {
+    offset: isize,
+    v: Vec<usize>,
+}
+
+impl V {
+    fn new(max_d: usize) -> V {
+        V {
+            offset: max_d as isize,
+            v: vec![0; 2 * max_d],
+        }
+    }
+}
+
+impl Index<isize> for V {
+    type Output = usize;
+
+    fn index(&self, k: isize) -> &usize {
+        &self.v[(k + self.offset) as usize]
+    }
+}
+
+impl IndexMut<isize> for V {
+    fn index_mut(&mut self, k: isize) -> &mut usize {
+        &mut self.v[(k + self.offset) as usize]
+    }
+}
+
+fn max_d(old_len: usize, new_len: usize) -> usize {
+    (old_len + new_len).div_ceil(2) + 1
+}
+
+/// Myers' O((N+M)D) diff in linear space.
+///
+/// Both sequences are searched from each end at the same time until the
+/// paths overlap on a "middle snake", then each half is solved recursively.
+/// Only two diagonal vectors are kept, so memory stays O(N+M) no matter how
+/// different the inputs are.
+pub(crate) fn diff<'a, T, F: Fn(&T, &T) -> bool>(
+    old: &'a [T],
+    new: &'a [T],
+    eq: &F,
+    budget: &Budget,
+    result: &mut Vec<DiffResult<'a, T>>,
+) {
+    let max_d = max_d(old.len(), new.len());
+    let mut myers = Myers {
+        eq,
+        budget,
+        vf: V::new(max_d),
+        vb: V::new(max_d),
+    };
+    myers.conquer(old, new, result);
+}
+
+struct Myers<'f, F> {
+    eq: &'f F,
+    budget: &'f Budget,
+    // Forward and backward furthest reaching paths
+    vf: V,
+    vb: V,
+}
+
+impl<'f, F> Myers<'f, F> {
+    fn find_middle_snake<T>(&mut self, old: &[T], new: &[T]) -> Option<(usize, usize)>
+    where
+        F: Fn(&T, &T) -> bool,
+    {
+        let eq = self.eq;
+        let (vf, vb) = (&mut self.vf, &mut self.vb);
+        let n = old.len();
+        let m = new.len();
+        let delta = n as isize - m as isize;
+        let odd = delta & 1 == 1;
+
+        vf[1] = 0;
+        vb[1] = 0;
+
+        let d_max = max_d(n, m) as isize;
+        for d in 0..d_max {
+            // Before the paths overlap at d, at least 2d - 1 edits are needed
+            if self.budget.exhausted()
+                || !self
+                    .budget
+                    .fits_edits(cmp::max(2 * d - 1, delta.abs()) as usize)
+            {
+                return None;
+            }
+            // Forward path
+            for k in (-d..=d).rev().step_by(2) {
+                let mut x = if k == -d || (k != d && vf[k - 1] < vf[k + 1]) {
+                    vf[k + 1]
+                } else {
+                    vf[k - 1] + 1
+                };
+                let y = (x as isize - k) as usize;
+                let (x0, y0) = (x, y);
+                if x < n && y < m {
+                    x += common_prefix_len(&old[x..], &new[y..], eq);
+                }
+                vf[k] = x;
+                if odd && (k - delta).abs() < d && vf[k] + vb[-(k - delta)] >= n {
+                    return Some((x0, y0));
+                }
+            }
+
+            // Backward path
+            for k in (-d..=d).rev().step_by(2) {
+                let mut x = if k == -d || (k != d && vb[k - 1] < vb[k + 1]) {
+                    vb[k + 1]
+                } else {
+                    vb[k - 1] + 1
+                };
+                let mut y = (x as isize - k) as usize;
+                if x < n && y < m {
+                    let advance = common_suffix_len(&old[..n - x], &new[..m - y], eq);
+                    x += advance;
+                    y += advance;
+                }
+                vb[k] = x;
+                if !odd && (k - delta).abs() <= d && vb[k] + vf[-(k - delta)] >= n {
+                    return Some((n - x, m - y));
+                }
+            }
+        }
+        None
+    }
+
+    // Align old and new, trimmed at each step so a snake always splits them
+    // into smaller parts
+    fn conquer<'a, T>(&mut self, old: &'a [T], new: &'a [T], result: &mut Vec<DiffResult<'a, T>>)
+    where
+        F: Fn(&T, &T) -> bool,
+    {
+        trim_common(old, new, self.eq, result, |old, new, result| {
+            let snake = if old.is_empty() || new.is_empty() {
+                self.budget.spend_edits(old.len() + new.len());
+                None
+            } else {
+                self.find_middle_snake(old, new)
+            };
+            match snake {
+                Some((x, y)) => {
+                    self.conquer(&old[..x], &new[..y], result);
+                    self.conquer(&old[x..], &new[y..], result);
+                }
+                None => replace_all(old, new, result),
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::diff;
+    use crate::Budget;
+
+    #[test]
+    fn should_give_up_past_max_edits() {
+        let old = "ABCABBA".chars().collect::<Vec<_>>();
+        let new = "CBABAC".chars().collect::<Vec<_>>();
+        let too_different = |old: &[char], new: &[char], max| {
+            let budget = Budget::max_edits(max);
+            diff(old, new, &|o, n| o == n, &budget, &mut vec![]);
+            budget.too_different()
+        };
+        assert!(!too_different(&old, &new, 5));
+        assert!(!too_different(&old, &new, 100));
+        assert!(too_different(&old, &new, 4));
+        assert!(!too_different(&old, &old, 0));
+        assert!(too_different(&old, &[], 6));
+        assert!(!too_different(&old, &[], 7));
+    }
+}
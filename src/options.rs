@@ -0,0 +1,447 @@
+use crate::{Rect, BLACK, BLUE, GRAY, GREEN, RATE, RED, YELLOW};
+use image::{DynamicImage, GrayAlphaImage};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// The sequence diff algorithm used to align the rows of two images
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Algorithm {
+    /// Myers' O((N+M)D) algorithm, fast when the images are mostly the same
+    #[default]
+    Myers,
+    /// Hirschberg's O(N·M) algorithm with guaranteed linear memory. Row
+    /// comparisons looking at neighbouring rows are not remembered, so with
+    /// [`ignore_anti_aliasing`](DiffOptionsBuilder::ignore_anti_aliasing)
+    /// it takes O(N·M·W) time for rows W pixels wide.
+    Hirschberg,
+    /// Patience diff, anchored on rows that are unique in both images
+    Patience,
+}
+
+impl FromStr for Algorithm {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "myers" => Ok(Algorithm::Myers),
+            "hirschberg" => Ok(Algorithm::Hirschberg),
+            "patience" => Ok(Algorithm::Patience),
+            _ => Err(format!("Unknown diff algorithm: {}", s)),
+        }
+    }
+}
+
+/// Whether images are diffed row by row, or column by column for content
+/// growing sideways
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Direction {
+    #[default]
+    Rows,
+    /// Added and removed columns are painted instead of rows. Every option
+    /// keeps its meaning with x and y swapped, the
+    /// [`WidthAlignment`] then applies to the heights of the images.
+    Columns,
+}
+
+impl FromStr for Direction {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "rows" => Ok(Direction::Rows),
+            "columns" => Ok(Direction::Columns),
+            _ => Err(format!("Unknown direction: {}", s)),
+        }
+    }
+}
+
+/// How the diff result image is painted
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum OutputMode {
+    /// The original rows, with added and removed rows tinted by their color
+    #[default]
+    Blend,
+    /// Added and removed rows in solid color and everything else
+    /// transparent, to be overlaid on top of either image
+    Mask,
+}
+
+impl FromStr for OutputMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "blend" => Ok(OutputMode::Blend),
+            "mask" => Ok(OutputMode::Mask),
+            _ => Err(format!("Unknown output mode: {}", s)),
+        }
+    }
+}
+
+/// How two pixels are decided to be equal when matching rows
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ColorMetric {
+    /// Every channel within the [`tolerance`](DiffOptionsBuilder::tolerance)
+    #[default]
+    PerChannel,
+    /// Perceptual YIQ distance as in pixelmatch, with a threshold from 0.0
+    /// (exact) to 1.0 (anything matches). 0.1 is a sensible default.
+    Yiq { threshold: f32 },
+    /// CIEDE2000 color difference, with a threshold in ΔE units. Differences
+    /// under about 2.3 are barely noticeable.
+    Ciede2000 { threshold: f32 },
+}
+
+impl FromStr for ColorMetric {
+    type Err = String;
+
+    /// Parse `channel`, `yiq` or `ciede2000`, optionally followed by a
+    /// threshold such as `yiq:0.05`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, threshold) = match s.split_once(':') {
+            Some((name, threshold)) => {
+                let threshold = threshold
+                    .parse::<f32>()
+                    .map_err(|_| format!("Invalid color threshold: {}", threshold))?;
+                (name, Some(threshold))
+            }
+            None => (s, None),
+        };
+        match name.to_ascii_lowercase().as_str() {
+            "channel" if threshold.is_none() => Ok(ColorMetric::PerChannel),
+            "yiq" => Ok(ColorMetric::Yiq {
+                threshold: threshold.unwrap_or(0.1),
+            }),
+            "ciede2000" => Ok(ColorMetric::Ciede2000 {
+                threshold: threshold.unwrap_or(2.3),
+            }),
+            _ => Err(format!("Unknown color metric: {}", s)),
+        }
+    }
+}
+
+/// Where the narrower image is placed when both images have different widths.
+/// The rest of its rows is padded with transparent black, and rows are
+/// compared as they are placed.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum WidthAlignment {
+    #[default]
+    Left,
+    Center,
+    Right,
+    /// Refuse to diff, with [`Error::DimensionMismatch`](crate::Error)
+    Fail,
+}
+
+impl FromStr for WidthAlignment {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_ascii_lowercase().as_str() {
+            "left" => Ok(WidthAlignment::Left),
+            "center" => Ok(WidthAlignment::Center),
+            "right" => Ok(WidthAlignment::Right),
+            "fail" => Ok(WidthAlignment::Fail),
+            _ => Err(format!("Unknown width alignment: {}", s)),
+        }
+    }
+}
+
+/// Options of [`diff_with_options`](crate::diff_with_options), created with
+/// [`DiffOptions::builder`]. The default options are the ones used by
+/// [`diff`](crate::diff).
+#[derive(Clone, Debug, PartialEq)]
+pub struct DiffOptions {
+    pub(crate) added_color: (u8, u8, u8),
+    pub(crate) removed_color: (u8, u8, u8),
+    pub(crate) changed_color: (u8, u8, u8),
+    pub(crate) moved_color: (u8, u8, u8),
+    pub(crate) ignored_color: (u8, u8, u8),
+    pub(crate) separator_color: (u8, u8, u8),
+    pub(crate) blend_rate: f32,
+    pub(crate) algorithm: Algorithm,
+    pub(crate) direction: Direction,
+    pub(crate) tolerance: u8,
+    pub(crate) max_differing_pixels: u32,
+    pub(crate) color_metric: ColorMetric,
+    pub(crate) ignore_anti_aliasing: bool,
+    pub(crate) min_moved_rows: Option<usize>,
+    pub(crate) min_similarity: Option<f32>,
+    pub(crate) horizontal_diff: bool,
+    pub(crate) ignore_rects: Vec<Rect>,
+    pub(crate) ignore_mask: Option<GrayAlphaImage>,
+    pub(crate) output_mode: OutputMode,
+    pub(crate) context_rows: Option<usize>,
+    pub(crate) max_pixels: Option<u64>,
+    pub(crate) max_edit_distance: Option<usize>,
+    pub(crate) work_budget: Option<u64>,
+    pub(crate) time_budget: Option<Duration>,
+    pub(crate) high_bit_depth: bool,
+    pub(crate) width_alignment: WidthAlignment,
+}
+
+impl Default for DiffOptions {
+    fn default() -> Self {
+        DiffOptions {
+            added_color: GREEN,
+            removed_color: RED,
+            changed_color: YELLOW,
+            moved_color: BLUE,
+            ignored_color: GRAY,
+            separator_color: BLACK,
+            blend_rate: RATE,
+            algorithm: Algorithm::default(),
+            direction: Direction::default(),
+            tolerance: 0,
+            max_differing_pixels: 0,
+            color_metric: ColorMetric::default(),
+            ignore_anti_aliasing: false,
+            min_moved_rows: None,
+            min_similarity: None,
+            horizontal_diff: false,
+            ignore_rects: vec![],
+            ignore_mask: None,
+            output_mode: OutputMode::default(),
+            context_rows: None,
+            max_pixels: None,
+            max_edit_distance: None,
+            work_budget: None,
+            time_budget: None,
+            high_bit_depth: false,
+            width_alignment: WidthAlignment::default(),
+        }
+    }
+}
+
+impl DiffOptions {
+    pub fn builder() -> DiffOptionsBuilder {
+        DiffOptionsBuilder::default()
+    }
+
+    // The same options for images with x and y swapped, diffed by rows
+    pub(crate) fn transposed(&self) -> DiffOptions {
+        DiffOptions {
+            direction: Direction::Rows,
+            ignore_rects: self.ignore_rects.iter().map(Rect::transposed).collect(),
+            ignore_mask: self.ignore_mask.as_ref().map(|mask| {
+                let mask = image::imageops::rotate90(mask);
+                image::imageops::flip_horizontal(&mask)
+            }),
+            ..self.clone()
+        }
+    }
+}
+
+/// Builder of [`DiffOptions`]
+#[derive(Clone, Debug, Default)]
+pub struct DiffOptionsBuilder {
+    options: DiffOptions,
+}
+
+impl DiffOptionsBuilder {
+    /// Highlight color of the rows only found in the after image
+    pub fn added_color(mut self, rgb: (u8, u8, u8)) -> Self {
+        self.options.added_color = rgb;
+        self
+    }
+
+    /// Highlight color of the rows only found in the before image
+    pub fn removed_color(mut self, rgb: (u8, u8, u8)) -> Self {
+        self.options.removed_color = rgb;
+        self
+    }
+
+    /// Highlight color of the differing pixels of changed rows
+    pub fn changed_color(mut self, rgb: (u8, u8, u8)) -> Self {
+        self.options.changed_color = rgb;
+        self
+    }
+
+    /// Highlight color of the rows of moved blocks
+    pub fn moved_color(mut self, rgb: (u8, u8, u8)) -> Self {
+        self.options.moved_color = rgb;
+        self
+    }
+
+    /// Color of the ignored pixels
+    pub fn ignored_color(mut self, rgb: (u8, u8, u8)) -> Self {
+        self.options.ignored_color = rgb;
+        self
+    }
+
+    /// Color of the bands standing for collapsed rows, see
+    /// [`context_rows`](Self::context_rows)
+    pub fn separator_color(mut self, rgb: (u8, u8, u8)) -> Self {
+        self.options.separator_color = rgb;
+        self
+    }
+
+    /// How much of the highlight color is blended into changed rows, from
+    /// 0.0 (original pixels) to 1.0 (solid color)
+    pub fn blend_rate(mut self, rate: f32) -> Self {
+        self.options.blend_rate = rate.clamp(0.0, 1.0);
+        self
+    }
+
+    /// Algorithm used to align the rows of both images
+    pub fn algorithm(mut self, algorithm: Algorithm) -> Self {
+        self.options.algorithm = algorithm;
+        self
+    }
+
+    /// Diff rows or columns
+    pub fn direction(mut self, direction: Direction) -> Self {
+        self.options.direction = direction;
+        self
+    }
+
+    /// Maximum difference of any channel for two pixels to be considered
+    /// equal. Rows are only matched when all of their pixels are equal,
+    /// unless [`max_differing_pixels`](Self::max_differing_pixels) is set.
+    pub fn tolerance(mut self, tolerance: u8) -> Self {
+        self.options.tolerance = tolerance;
+        self
+    }
+
+    /// Number of pixels that may differ between two rows still considered
+    /// equal, for isolated rendering noise
+    pub fn max_differing_pixels(mut self, max_differing_pixels: u32) -> Self {
+        self.options.max_differing_pixels = max_differing_pixels;
+        self
+    }
+
+    /// Metric deciding whether two pixels are equal, the tolerance only
+    /// applies to [`ColorMetric::PerChannel`]
+    pub fn color_metric(mut self, color_metric: ColorMetric) -> Self {
+        self.options.color_metric = color_metric;
+        self
+    }
+
+    /// Do not count pixels detected as anti-aliased edges as differences, so
+    /// rows that only differ by their anti-aliasing are aligned as common
+    /// and left unhighlighted
+    pub fn ignore_anti_aliasing(mut self, ignore_anti_aliasing: bool) -> Self {
+        self.options.ignore_anti_aliasing = ignore_anti_aliasing;
+        self
+    }
+
+    /// Report blocks of at least `min_rows` removed rows added back
+    /// elsewhere as moved, in the [`moved_color`](Self::moved_color) at both
+    /// places
+    pub fn detect_moves(mut self, min_rows: usize) -> Self {
+        self.options.min_moved_rows = Some(min_rows);
+        self
+    }
+
+    /// Pair each removed row with the added row replacing it when at least
+    /// `min_similarity` (0.0 to 1.0) of their pixels match. Paired rows are
+    /// rendered once, with only their differing pixels highlighted in the
+    /// [`changed_color`](Self::changed_color).
+    ///
+    /// Rows are paired in order within each block of changes: the first
+    /// removed row is only compared to the first added row, the second to
+    /// the second, and so on. A similar row added at another position in the
+    /// block stays unpaired.
+    pub fn pair_changed_rows(mut self, min_similarity: f32) -> Self {
+        self.options.min_similarity = Some(min_similarity.clamp(0.0, 1.0));
+        self
+    }
+
+    /// Diff the pixels of changed rows along the x axis, so content inserted
+    /// horizontally is highlighted in the [`added_color`](Self::added_color)
+    /// instead of every shifted pixel. Content deleted horizontally is marked
+    /// in the [`removed_color`](Self::removed_color) on the pixel of the after
+    /// row where it was cut out, or in the
+    /// [`changed_color`](Self::changed_color) when that pixel is added too.
+    /// Their similarity is then the ratio of pixels both rows have in common.
+    /// Only used with [`pair_changed_rows`](Self::pair_changed_rows).
+    pub fn horizontal_diff(mut self, horizontal_diff: bool) -> Self {
+        self.options.horizontal_diff = horizontal_diff;
+        self
+    }
+
+    /// Exclude a rectangle from the comparison of both images, it is painted
+    /// with the [`ignored_color`](Self::ignored_color). Can be called
+    /// several times.
+    pub fn ignore_rect(mut self, rect: Rect) -> Self {
+        self.options.ignore_rects.push(rect);
+        self
+    }
+
+    /// Exclude the pixels where the mask is not fully transparent from the
+    /// comparison of both images, the same way as
+    /// [`ignore_rect`](Self::ignore_rect). The mask is laid over the top left
+    /// corner of each image.
+    pub fn ignore_mask(mut self, mask: &DynamicImage) -> Self {
+        self.options.ignore_mask = Some(mask.to_luma_alpha8());
+        self
+    }
+
+    pub fn output_mode(mut self, output_mode: OutputMode) -> Self {
+        self.options.output_mode = output_mode;
+        self
+    }
+
+    /// Condense the result image to the changed rows and `context` common
+    /// rows around them, like `diff -u`. Every other stretch of common rows
+    /// taller than 4 rows is replaced by a band of 4 rows in the
+    /// [`separator_color`](Self::separator_color).
+    pub fn context_rows(mut self, context: usize) -> Self {
+        self.options.context_rows = Some(context);
+        self
+    }
+
+    /// Largest number of pixels allowed in either input and in the result.
+    /// Larger diffs fail with [`Error::SizeLimitExceeded`](crate::Error)
+    /// instead of allocating.
+    pub fn max_pixels(mut self, max_pixels: u64) -> Self {
+        self.options.max_pixels = Some(max_pixels);
+        self
+    }
+
+    /// Largest number of rows to remove from the before image and add to
+    /// get the after image. The alignment gives up as soon as more are
+    /// needed, and images that different fail with
+    /// [`Error::TooDifferent`](crate::Error) instead of being diffed.
+    pub fn max_edit_distance(mut self, max_edit_distance: usize) -> Self {
+        self.options.max_edit_distance = Some(max_edit_distance);
+        self
+    }
+
+    /// Largest number of row comparisons the alignment of both images may
+    /// take. Past it, rows are only compared at the same position once the
+    /// common top and bottom rows are set aside, and the
+    /// [`DiffStats`](crate::DiffStats) of the diff are marked approximate.
+    /// Looking for moved rows and diffing changed rows horizontally take
+    /// from the same budget, and stop once it is spent.
+    pub fn work_budget(mut self, comparisons: u64) -> Self {
+        self.options.work_budget = Some(comparisons);
+        self
+    }
+
+    /// Longest time the alignment of both images may take, past which it
+    /// falls back to comparing rows at the same position like the
+    /// [`work_budget`](Self::work_budget).
+    pub fn time_budget(mut self, time_budget: Duration) -> Self {
+        self.options.time_budget = Some(time_budget);
+        self
+    }
+
+    /// Compare 16 bit images at full precision and return an RGBA16 result
+    /// when both inputs are 16 bit. Otherwise everything is compared and
+    /// returned as RGBA8.
+    pub fn high_bit_depth(mut self, high_bit_depth: bool) -> Self {
+        self.options.high_bit_depth = high_bit_depth;
+        self
+    }
+
+    /// How images of different widths are aligned with each other
+    pub fn width_alignment(mut self, width_alignment: WidthAlignment) -> Self {
+        self.options.width_alignment = width_alignment;
+        self
+    }
+
+    pub fn build(self) -> DiffOptions {
+        self.options
+    }
+}
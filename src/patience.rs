@@ -0,0 +1,138 @@
+use crate::{myers, replace_all, Budget, DiffElement, DiffResult};
+use std::collections::HashMap;
+use std::hash::Hash;
+
+/// Patience diff.
+///
+/// Elements that occur exactly once in both sequences are used as anchors:
+/// the longest run of anchors appearing in the same order on both sides is
+/// kept as common, and the gaps between them are diffed recursively. Gaps
+/// without any unique element fall back to Myers. Repetitive rows such as
+/// blank background never become anchors, so a change stays one coherent
+/// block instead of being spread over whatever blank rows happen to line up.
+///
+/// Anchors are always exact matches, `eq` is used for everything else.
+pub(crate) fn diff<'a, T: Hash + Eq, F: Fn(&T, &T) -> bool>(
+    old: &'a [T],
+    new: &'a [T],
+    eq: &F,
+    budget: &Budget,
+    result: &mut Vec<DiffResult<'a, T>>,
+) {
+    if old.is_empty() || new.is_empty() {
+        budget.spend_edits(old.len() + new.len());
+        replace_all(old, new, result);
+    } else if budget.exhausted() || !budget.fits_edits(old.len().abs_diff(new.len())) {
+        replace_all(old, new, result);
+    } else {
+        let anchors = unique_anchors(old, new);
+        if anchors.is_empty() {
+            myers::diff(old, new, eq, budget, result);
+        } else {
+            let mut o = 0;
+            let mut n = 0;
+            for (anchor_o, anchor_n) in anchors {
+                diff(&old[o..anchor_o], &new[n..anchor_n], eq, budget, result);
+                result.push(DiffResult::Common(DiffElement {
+                    data: &old[anchor_o],
+                }));
+                o = anchor_o + 1;
+                n = anchor_n + 1;
+            }
+            diff(&old[o..], &new[n..], eq, budget, result);
+        }
+    }
+}
+
+// Longest chain of (old index, new index) pairs of elements unique in both
+// sequences, increasing on both sides
+fn unique_anchors<T: Hash + Eq>(old: &[T], new: &[T]) -> Vec<(usize, usize)> {
+    // element -> (count in old, index in old, count in new, index in new)
+    let mut occurrences: HashMap<&T, (u32, usize, u32, usize)> = HashMap::new();
+    for (i, o) in old.iter().enumerate() {
+        let entry = occurrences.entry(o).or_insert((0, i, 0, 0));
+        entry.0 += 1;
+    }
+    for (j, n) in new.iter().enumerate() {
+        if let Some(entry) = occurrences.get_mut(n) {
+            entry.2 += 1;
+            entry.3 = j;
+        }
+    }
+    let mut candidates = occurrences
+        .into_values()
+        .filter(|&(old_count, _, new_count, _)| old_count == 1 && new_count == 1)
+        .map(|(_, i, _, j)| (i, j))
+        .collect::<Vec<_>>();
+    candidates.sort_unstable();
+    longest_increasing_chain(&candidates)
+}
+
+// Patience sorting on the new indices of candidates already sorted by old index
+fn longest_increasing_chain(candidates: &[(usize, usize)]) -> Vec<(usize, usize)> {
+    // Index into candidates of the top card of each pile
+    let mut piles: Vec<usize> = vec![];
+    // Top card of the previous pile when each candidate was placed
+    let mut predecessors = vec![None; candidates.len()];
+    for (c, &(_, j)) in candidates.iter().enumerate() {
+        let pile = piles.partition_point(|&top| candidates[top].1 < j);
+        if pile > 0 {
+            predecessors[c] = Some(piles[pile - 1]);
+        }
+        if pile == piles.len() {
+            piles.push(c);
+        } else {
+            piles[pile] = c;
+        }
+    }
+    let mut chain = vec![];
+    let mut card = piles.last().copied();
+    while let Some(c) = card {
+        chain.push(candidates[c]);
+        card = predecessors[c];
+    }
+    chain.reverse();
+    chain
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::replay;
+    use crate::{lcs_diff, Algorithm};
+
+    #[test]
+    fn should_chain_anchors_in_order() {
+        let candidates = [(0, 3), (1, 0), (2, 1), (3, 4), (4, 2)];
+        assert_eq!(
+            vec![(1, 0), (2, 1), (4, 2)],
+            longest_increasing_chain(&candidates)
+        );
+    }
+
+    #[test]
+    fn should_fall_back_to_myers_without_unique_rows() {
+        // "b" is the blank background row, "H" and "F" are unique
+        let old = ["H", "b", "b", "F"];
+        let new = ["H", "b", "x", "b", "b", "y", "F"];
+        let script = lcs_diff(&old, &new, Algorithm::Patience);
+        let (replayed_old, replayed_new, common) = replay(&script);
+        assert_eq!(old.iter().collect::<Vec<_>>(), replayed_old);
+        assert_eq!(new.iter().collect::<Vec<_>>(), replayed_new);
+        assert_eq!(4, common);
+    }
+
+    #[test]
+    fn should_anchor_on_unique_rows() {
+        let old = ["b", "A", "b", "b", "B", "b"];
+        let new = ["b", "B", "b", "b", "A", "b"];
+        let script = lcs_diff(&old, &new, Algorithm::Patience);
+        let (replayed_old, replayed_new, _) = replay(&script);
+        assert_eq!(old.iter().collect::<Vec<_>>(), replayed_old);
+        assert_eq!(new.iter().collect::<Vec<_>>(), replayed_new);
+        // Only one of the swapped unique rows can be an anchor
+        assert!(script
+            .iter()
+            .any(|d| matches!(d, DiffResult::Common(c) if *c.data == "A" || *c.data == "B")));
+    }
+}
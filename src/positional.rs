@@ -0,0 +1,73 @@
+use crate::{replace_all, trim_common, DiffElement, DiffResult};
+use std::cmp;
+use std::ops::Range;
+
+/// Cheap alignment for when the diff engines run out of budget.
+///
+/// The common prefix and suffix are trimmed, then the elements left are
+/// compared at the same position of both sequences. Runs of differing
+/// positions are replaced as a whole, and the extra elements of the longer
+/// sequence are removed or added at the end. It takes O(N+M) comparisons but
+/// cannot tell an insertion in the middle from a change of every row below
+/// it.
+pub(crate) fn diff<'a, T, F: Fn(&T, &T) -> bool>(
+    old: &'a [T],
+    new: &'a [T],
+    eq: &F,
+    result: &mut Vec<DiffResult<'a, T>>,
+) {
+    trim_common(old, new, eq, result, |old, new, result| {
+        // Start of the run of differing positions
+        let mut start = 0;
+        for i in 0..cmp::min(old.len(), new.len()) {
+            if eq(&old[i], &new[i]) {
+                replace(old, new, start..i, result);
+                result.push(DiffResult::Common(DiffElement { data: &old[i] }));
+                start = i + 1;
+            }
+        }
+        replace(old, new, start..cmp::max(old.len(), new.len()), result);
+    });
+}
+
+// Replace the elements of old with the elements of new in the range of
+// positions, as far as each sequence goes
+fn replace<'a, T>(
+    old: &'a [T],
+    new: &'a [T],
+    positions: Range<usize>,
+    result: &mut Vec<DiffResult<'a, T>>,
+) {
+    let clamp = |len: usize| cmp::min(positions.start, len)..cmp::min(positions.end, len);
+    replace_all(&old[clamp(old.len())], &new[clamp(new.len())], result);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::tests::replay;
+
+    #[test]
+    fn should_compare_rows_at_the_same_position() {
+        let old = ["a", "b", "c", "d", "e"];
+        let new = ["a", "x", "c", "d", "y", "z", "e"];
+        let mut script = vec![];
+        diff(&old, &new, &|o, n| o == n, &mut script);
+        let (replayed_old, replayed_new, common) = replay(&script);
+        assert_eq!(old.iter().collect::<Vec<_>>(), replayed_old);
+        assert_eq!(new.iter().collect::<Vec<_>>(), replayed_new);
+        assert_eq!(4, common);
+        assert!(matches!(&script[1], DiffResult::Added(a) if *a.data == "x"));
+        assert!(matches!(&script[2], DiffResult::Removed(r) if *r.data == "b"));
+    }
+
+    #[test]
+    fn should_not_find_insertions_in_the_middle() {
+        let old = ["a", "b", "c", "d"];
+        let new = ["a", "x", "b", "c", "d", "e"];
+        let mut script = vec![];
+        diff(&old, &new, &|o, n| o == n, &mut script);
+        let (_, _, common) = replay(&script);
+        assert_eq!(1, common);
+    }
+}
@@ -0,0 +1,213 @@
+use crate::Rect;
+use serde::Serialize;
+use std::ops::Range;
+
+/// A group of touching highlighted pixels of the diff image, with the
+/// rectangles it covers in each image.
+///
+/// A region made of added rows only has an empty `before` rectangle where the
+/// rows were inserted, and likewise for removed rows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct ChangedRegion {
+    /// Bounding rectangle in the diff image
+    pub diff: Rect,
+    /// Rectangle of the before image the region comes from
+    pub before: Rect,
+    /// Rectangle of the after image the region comes from
+    pub after: Rect,
+}
+
+impl ChangedRegion {
+    /// Same region with x and y swapped
+    pub(crate) fn transposed(self) -> Self {
+        ChangedRegion {
+            diff: self.diff.transposed(),
+            before: self.before.transposed(),
+            after: self.after.transposed(),
+        }
+    }
+}
+
+/// Group consecutive x into runs
+pub(crate) fn runs(xs: impl Iterator<Item = u32>) -> Vec<Range<u32>> {
+    let mut runs: Vec<Range<u32>> = vec![];
+    for x in xs {
+        match runs.last_mut() {
+            Some(run) if run.end == x => run.end += 1,
+            _ => runs.push(x..x + 1),
+        }
+    }
+    runs
+}
+
+// Runs of highlighted pixels of the diff image, grouped into regions as rows
+// are added, along with the rows of each image every diff row comes from
+#[derive(Default)]
+pub(crate) struct RegionFinder {
+    // Run and row of the diff image of each run
+    runs: Vec<(Range<u32>, u32)>,
+    // Union-find forest over the runs
+    parents: Vec<usize>,
+    // Runs of the last row having runs
+    last_row: Range<usize>,
+    // Rows of the before and after image of each diff row, empty where the
+    // diff row is not found in that image
+    sources: Vec<(Range<u32>, Range<u32>)>,
+}
+
+impl RegionFinder {
+    fn root(&mut self, mut run: usize) -> usize {
+        while self.parents[run] != run {
+            self.parents[run] = self.parents[self.parents[run]];
+            run = self.parents[run];
+        }
+        run
+    }
+
+    /// Add the next row of the diff image, with its highlighted runs and the
+    /// rows it comes from
+    pub(crate) fn add_row(&mut self, runs: Vec<Range<u32>>, before: Range<u32>, after: Range<u32>) {
+        let y = self.sources.len() as u32;
+        self.sources.push((before, after));
+        let previous = match self.last_row.clone() {
+            row if !row.is_empty() && self.runs[row.start].1 + 1 == y => row,
+            _ => 0..0,
+        };
+        let start = self.runs.len();
+        for run in runs {
+            let index = self.runs.len();
+            self.parents.push(index);
+            // Diagonal neighbours touch too
+            for other in previous.clone() {
+                let other_run = &self.runs[other].0;
+                if other_run.start <= run.end && run.start <= other_run.end {
+                    let (a, b) = (self.root(other), self.root(index));
+                    self.parents[b] = a;
+                }
+            }
+            self.runs.push((run, y));
+        }
+        if self.runs.len() > start {
+            self.last_row = start..self.runs.len();
+        }
+    }
+
+    /// The regions found, in the order of their first row. Regions whose
+    /// rectangles overlap are merged. `before_place` and `after_place` are
+    /// the width of each image and its offset on the diff image.
+    pub(crate) fn regions(
+        mut self,
+        before_place: (u32, u32),
+        after_place: (u32, u32),
+    ) -> Vec<ChangedRegion> {
+        let mut rects: Vec<Rect> = vec![];
+        let mut rect_of_root: Vec<Option<usize>> = vec![None; self.runs.len()];
+        for index in 0..self.runs.len() {
+            let root = self.root(index);
+            let (run, y) = &self.runs[index];
+            let rect = Rect::new(run.start, *y, run.end - run.start, 1);
+            match rect_of_root[root] {
+                Some(i) => rects[i] = rects[i].union(&rect),
+                None => {
+                    rect_of_root[root] = Some(rects.len());
+                    rects.push(rect);
+                }
+            }
+        }
+        let mut merged: Vec<Rect> = vec![];
+        for rect in rects {
+            let mut rect = rect;
+            // Absorb every region the growing rectangle overlaps
+            while let Some(i) = merged.iter().position(|other| other.overlaps(&rect)) {
+                rect = rect.union(&merged.remove(i));
+            }
+            merged.push(rect);
+        }
+        merged.sort_by_key(|rect| (rect.y, rect.x));
+        merged
+            .into_iter()
+            .map(|diff| {
+                let rows = &self.sources[diff.y as usize..(diff.y + diff.height) as usize];
+                ChangedRegion {
+                    diff,
+                    before: source_rect(&diff, rows.iter().map(|r| &r.0), before_place),
+                    after: source_rect(&diff, rows.iter().map(|r| &r.1), after_place),
+                }
+            })
+            .collect()
+    }
+}
+
+// Rectangle of an image placed at offset on the diff image, covering the given
+// rectangle of the diff image, rows being the rows of the image of each diff
+// row
+fn source_rect<'a>(
+    diff: &Rect,
+    rows: impl Iterator<Item = &'a Range<u32>>,
+    (src_width, offset): (u32, u32),
+) -> Rect {
+    let x = diff.x.saturating_sub(offset).min(src_width);
+    let right = (diff.x + diff.width).saturating_sub(offset).min(src_width);
+    let (y, bottom) = rows.fold((u32::MAX, 0), |(y, bottom), r| {
+        (y.min(r.start), bottom.max(r.end))
+    });
+    Rect::new(x, y, right - x, bottom - y)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_group_consecutive_x_into_runs() {
+        assert_eq!(vec![1..3, 5..6], runs([1, 2, 5].into_iter()));
+        assert!(runs([].into_iter()).is_empty());
+    }
+
+    #[test]
+    fn should_find_touching_runs() {
+        let mut finder = RegionFinder::default();
+        // A diagonal line at the top left, and a block at the right rendered
+        // from removed rows 1 and 2 of the before image
+        finder.add_row(runs(0..1), 0..1, 0..1);
+        finder.add_row(vec![1..2, 6..8], 1..2, 1..1);
+        finder.add_row(runs(6..8), 2..3, 1..1);
+        finder.add_row(vec![], 3..4, 1..2);
+        finder.add_row(runs(3..4), 4..5, 2..3);
+        assert_eq!(
+            vec![
+                ChangedRegion {
+                    diff: Rect::new(0, 0, 2, 2),
+                    before: Rect::new(0, 0, 2, 2),
+                    after: Rect::new(0, 0, 2, 1),
+                },
+                ChangedRegion {
+                    diff: Rect::new(6, 1, 2, 2),
+                    before: Rect::new(6, 1, 2, 2),
+                    after: Rect::new(6, 1, 2, 0),
+                },
+                ChangedRegion {
+                    diff: Rect::new(3, 4, 1, 1),
+                    before: Rect::new(3, 4, 1, 1),
+                    after: Rect::new(3, 2, 1, 1),
+                },
+            ],
+            finder.regions((10, 0), (10, 0))
+        );
+    }
+
+    #[test]
+    fn should_merge_overlapping_regions() {
+        // A U shape holding a dot
+        let mut finder = RegionFinder::default();
+        finder.add_row(vec![0..1, 4..5], 0..1, 0..1);
+        finder.add_row(vec![0..1, 2..3, 4..5], 1..2, 1..2);
+        finder.add_row(vec![0..1, 4..5], 2..3, 2..3);
+        finder.add_row(runs(0..5), 3..4, 3..4);
+        let regions = finder.regions((3, 2), (5, 0));
+        assert_eq!(1, regions.len());
+        assert_eq!(Rect::new(0, 0, 5, 4), regions[0].diff);
+        // The narrower before image is centered on the diff image
+        assert_eq!(Rect::new(0, 0, 3, 4), regions[0].before);
+    }
+}
@@ -0,0 +1,283 @@
+//! Diff of any two sequences, with the same engines the rows of images are
+//! aligned with.
+//!
+//! ```
+//! use lcs_png_diff::sequence::{diff, edit_distance, lcs, Edit};
+//!
+//! let old = ["a", "b", "c"];
+//! let new = ["a", "c", "d"];
+//! assert_eq!(
+//!     vec![
+//!         Edit::Common(&"a"),
+//!         Edit::Removed(&"b"),
+//!         Edit::Common(&"c"),
+//!         Edit::Added(&"d"),
+//!     ],
+//!     diff(&old, &new)
+//! );
+//! assert_eq!(vec![&"a", &"c"], lcs(&old, &new));
+//! assert_eq!(2, edit_distance(&old, &new));
+//! ```
+
+use crate::{lcs_diff_by, myers, Algorithm, Budget, DiffResult};
+use serde::Serialize;
+use std::cmp;
+use std::hash::Hash;
+
+/// An operation of the edit script turning one sequence into another.
+///
+/// [`diff`] returns operations borrowing the elements of both sequences,
+/// [`Edit::cloned`] turns them into operations owning their element.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash, Serialize)]
+#[serde(tag = "op", content = "element", rename_all = "snake_case")]
+pub enum Edit<T> {
+    /// An element of both sequences, as found in the old one
+    Common(T),
+    /// An element only found in the old sequence
+    Removed(T),
+    /// An element only found in the new sequence
+    Added(T),
+}
+
+impl<T> Edit<T> {
+    /// The element of the operation
+    pub fn element(&self) -> &T {
+        match self {
+            Edit::Common(e) | Edit::Removed(e) | Edit::Added(e) => e,
+        }
+    }
+
+    /// The same operation on another element
+    pub fn map<U, F: FnOnce(T) -> U>(self, f: F) -> Edit<U> {
+        match self {
+            Edit::Common(e) => Edit::Common(f(e)),
+            Edit::Removed(e) => Edit::Removed(f(e)),
+            Edit::Added(e) => Edit::Added(f(e)),
+        }
+    }
+}
+
+impl<T: Clone> Edit<&T> {
+    /// The same operation owning a clone of its element
+    pub fn cloned(self) -> Edit<T> {
+        self.map(T::clone)
+    }
+}
+
+/// The shortest edit script turning `old` into `new`, with Myers' algorithm
+pub fn diff<'a, T: PartialEq>(old: &'a [T], new: &'a [T]) -> Vec<Edit<&'a T>> {
+    let mut script = Vec::with_capacity(cmp::max(old.len(), new.len()));
+    myers::diff(
+        old,
+        new,
+        &|o: &T, n: &T| o == n,
+        &Budget::unlimited(),
+        &mut script,
+    );
+    to_edits(script)
+}
+
+/// Same as [`diff`], with the given algorithm
+pub fn diff_with<'a, T: Hash + Eq>(
+    old: &'a [T],
+    new: &'a [T],
+    algorithm: Algorithm,
+) -> Vec<Edit<&'a T>> {
+    diff_by(old, new, algorithm, |o: &T, n: &T| o == n)
+}
+
+/// Same as [`diff_with`], elements being equal when `eq` says so. `eq` must
+/// at least hold for elements equal under `==`, which
+/// [`Algorithm::Patience`] relies on to find unique elements.
+pub fn diff_by<'a, T: Hash + Eq, F: Fn(&T, &T) -> bool>(
+    old: &'a [T],
+    new: &'a [T],
+    algorithm: Algorithm,
+    eq: F,
+) -> Vec<Edit<&'a T>> {
+    to_edits(lcs_diff_by(old, new, algorithm, &eq, &Budget::unlimited()))
+}
+
+// The edits of a script of the diff engines
+fn to_edits<T>(script: Vec<DiffResult<'_, T>>) -> Vec<Edit<&T>> {
+    script
+        .into_iter()
+        .map(|d| match d {
+            DiffResult::Common(e) => Edit::Common(e.data),
+            DiffResult::Removed(e) => Edit::Removed(e.data),
+            DiffResult::Added(e) => Edit::Added(e.data),
+            _ => unreachable!("the diff engines only produce common, removed and added elements"),
+        })
+        .collect()
+}
+
+/// A longest common subsequence of both sequences, borrowed from `old`
+pub fn lcs<'a, T: PartialEq>(old: &'a [T], new: &'a [T]) -> Vec<&'a T> {
+    diff(old, new)
+        .into_iter()
+        .filter_map(|edit| match edit {
+            Edit::Common(e) => Some(e),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Number of elements to remove from `old` and add to get `new`
+pub fn edit_distance<T: PartialEq>(old: &[T], new: &[T]) -> usize {
+    diff(old, new)
+        .iter()
+        .filter(|edit| !matches!(edit, Edit::Common(_)))
+        .count()
+}
+
+/// Same as [`edit_distance`] when it is at most `max`, None otherwise. It
+/// takes O((N+M)·max) time, giving up early on very different sequences.
+pub fn edit_distance_within<T: PartialEq>(old: &[T], new: &[T], max: usize) -> Option<usize> {
+    let budget = Budget::max_edits(max);
+    let mut script = vec![];
+    myers::diff(old, new, &|o: &T, n: &T| o == n, &budget, &mut script);
+    let edits = script
+        .iter()
+        .filter(|d| !matches!(d, DiffResult::Common(_)))
+        .count();
+    (!budget.too_different()).then_some(edits)
+}
+
+/// The dynamic programming table of the lengths of the longest common
+/// subsequences of every pair of suffixes of both sequences. It takes
+/// O(N·M) time and memory, [`lcs`] is much cheaper.
+///
+/// Table is like:
+/// ```text
+/// \ o l d
+/// n
+/// e
+/// w
+/// ```
+pub fn create_table<T: PartialEq>(old: &[T], new: &[T]) -> Vec<Vec<u32>> {
+    let new_len = new.len();
+    let old_len = old.len();
+    let mut table = vec![vec![0; old_len + 1]; new_len + 1];
+    for i in 0..new_len {
+        let i = new_len - i - 1;
+        for j in 0..old_len {
+            let j = old_len - j - 1;
+            // Performance bottle neck - long string comparison
+            table[i][j] = if new[i] == old[j] {
+                table[i + 1][j + 1] + 1
+            } else {
+                cmp::max(table[i + 1][j], table[i][j + 1])
+            }
+        }
+    }
+    table
+}
+
+/// The longest common subsequence read from the table of [`create_table`],
+/// borrowed from `new`
+pub fn gen_lcs<'a, T: PartialEq>(table: &[Vec<u32>], old: &[T], new: &'a [T]) -> Vec<&'a T> {
+    let o_len = old.len();
+    let n_len = new.len();
+    let mut o = 0;
+    let mut n = 0;
+    let mut res = vec![];
+    while o < o_len && n < n_len {
+        if old[o] == new[n] {
+            res.push(&new[n]);
+            o += 1;
+            n += 1; // Common
+        } else if table[n + 1][o] >= table[n][o + 1] {
+            n += 1; // Add from new
+        } else {
+            o += 1; // Remove from old
+        }
+    }
+    res
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn should_diff_with_every_algorithm() {
+        let old = "the quick brown fox jumps".split(' ').collect::<Vec<_>>();
+        let new = "the brown fox jumps high".split(' ').collect::<Vec<_>>();
+        for algorithm in [Algorithm::Myers, Algorithm::Hirschberg, Algorithm::Patience] {
+            let edits = diff_with(&old, &new, algorithm);
+            assert_eq!(
+                vec![
+                    Edit::Common("the"),
+                    Edit::Removed("quick"),
+                    Edit::Common("brown"),
+                    Edit::Common("fox"),
+                    Edit::Common("jumps"),
+                    Edit::Added("high"),
+                ],
+                edits.into_iter().map(Edit::cloned).collect::<Vec<_>>()
+            );
+        }
+    }
+
+    #[test]
+    fn should_diff_by_custom_equality() {
+        let old = ["Foo", "bar"];
+        let new = ["foo", "BAR", "baz"];
+        let edits = diff_by(&old, &new, Algorithm::Myers, |o, n| {
+            o.eq_ignore_ascii_case(n)
+        });
+        assert_eq!(
+            vec![
+                Edit::Common(&"Foo"),
+                Edit::Common(&"bar"),
+                Edit::Added(&"baz")
+            ],
+            edits
+        );
+    }
+
+    #[test]
+    fn should_agree_with_table() {
+        let old = "ABCBDAB".chars().collect::<Vec<_>>();
+        let new = "BDCABA".chars().collect::<Vec<_>>();
+        let table = create_table(&old, &new);
+        assert_eq!(table[0][0] as usize, lcs(&old, &new).len());
+        assert_eq!(table[0][0] as usize, gen_lcs(&table, &old, &new).len());
+        assert_eq!(
+            old.len() + new.len() - 2 * table[0][0] as usize,
+            edit_distance(&old, &new)
+        );
+        assert_eq!(0, edit_distance(&old, &old));
+        assert_eq!(
+            Some(edit_distance(&old, &new)),
+            edit_distance_within(&old, &new, 5)
+        );
+        assert_eq!(None, edit_distance_within(&old, &new, 4));
+    }
+
+    #[test]
+    fn should_diff_elements_without_hash() {
+        let old = [0.5, 1.0, 2.5];
+        let new = [1.0, 2.5, 4.0];
+        assert_eq!(
+            vec![
+                Edit::Removed(&0.5),
+                Edit::Common(&1.0),
+                Edit::Common(&2.5),
+                Edit::Added(&4.0),
+            ],
+            diff(&old, &new)
+        );
+        assert_eq!(vec![&1.0, &2.5], lcs(&old, &new));
+        assert_eq!(2, edit_distance(&old, &new));
+        assert_eq!(None, edit_distance_within(&old, &new, 1));
+    }
+
+    #[test]
+    fn should_serialize_edit() {
+        assert_eq!(
+            r#"{"op":"added","element":"x"}"#,
+            serde_json::to_string(&Edit::Added("x")).unwrap()
+        );
+    }
+}
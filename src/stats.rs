@@ -0,0 +1,147 @@
+use crate::{Hunk, HunkKind, Rect};
+use serde::Serialize;
+use std::ops::Range;
+
+/// Numbers summing up a diff. Rows are columns when diffing
+/// [`Direction::Columns`](crate::Direction::Columns).
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct DiffStats {
+    /// Rows only found in the after image
+    pub added_rows: usize,
+    /// Rows only found in the before image
+    pub removed_rows: usize,
+    /// Rows found in both images
+    pub common_rows: usize,
+    /// Rows of the before image replaced by similar rows
+    pub changed_rows: usize,
+    /// Rows of the before image found at another place in the after image
+    pub moved_rows: usize,
+    /// Highlighted pixels of the diff image, ignored pixels excepted
+    pub changed_pixels: u64,
+    /// Percentage of the pixels of the diff that are highlighted, counting the
+    /// rows collapsed by
+    /// [`context_rows`](crate::DiffOptionsBuilder::context_rows)
+    pub changed_percentage: f64,
+    /// Smallest rectangle of the diff image holding every highlighted pixel
+    pub bounds: Option<Rect>,
+    /// Whether no difference was found between images of the same size
+    pub identical: bool,
+    /// Whether the alignment ran out of its
+    /// [`work_budget`](crate::DiffOptionsBuilder::work_budget) or
+    /// [`time_budget`](crate::DiffOptionsBuilder::time_budget), so rows were
+    /// only compared at the same position, or moved and changed rows were
+    /// not all looked for, and more of them may be reported as added and
+    /// removed than needed
+    pub approximate: bool,
+}
+
+impl DiffStats {
+    /// Count the rows of each kind of hunk
+    pub(crate) fn new(hunks: &[Hunk]) -> Self {
+        let mut stats = DiffStats::default();
+        for hunk in hunks {
+            let len = hunk.before.len().max(hunk.after.len());
+            match hunk.kind {
+                HunkKind::Common => stats.common_rows += len,
+                HunkKind::Added => stats.added_rows += len,
+                HunkKind::Removed => stats.removed_rows += len,
+                HunkKind::Changed => stats.changed_rows += len,
+                HunkKind::Moved => stats.moved_rows += len,
+            }
+        }
+        stats
+    }
+
+    /// Count the highlighted pixels of row y of the diff image, given as runs
+    /// of consecutive x
+    pub(crate) fn add_changed_runs(&mut self, y: u32, runs: &[Range<u32>]) {
+        if let (Some(first), Some(last)) = (runs.first(), runs.last()) {
+            self.changed_pixels += runs.iter().map(|run| run.len() as u64).sum::<u64>();
+            let row = Rect::new(first.start, y, last.end - first.start, 1);
+            self.bounds = Some(self.bounds.map_or(row, |bounds| bounds.union(&row)));
+        }
+    }
+
+    /// Fill in the totals once every row of the diff image is counted
+    pub(crate) fn finish(&mut self, (width, height): (u32, u32), same_size: bool) {
+        let pixels = width as u64 * height as u64;
+        if pixels > 0 {
+            self.changed_percentage = self.changed_pixels as f64 * 100.0 / pixels as f64;
+        }
+        self.identical = same_size
+            && self.changed_pixels == 0
+            && self.added_rows + self.removed_rows + self.changed_rows + self.moved_rows == 0;
+    }
+
+    /// Same stats with x and y swapped
+    pub(crate) fn transposed(self) -> Self {
+        DiffStats {
+            bounds: self.bounds.as_ref().map(Rect::transposed),
+            ..self
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::regions::runs;
+
+    #[test]
+    fn should_sum_up_rows_and_pixels() {
+        let hunks = [
+            Hunk {
+                kind: HunkKind::Common,
+                before: 0..3,
+                after: 0..3,
+            },
+            Hunk {
+                kind: HunkKind::Removed,
+                before: 3..5,
+                after: 3..3,
+            },
+            Hunk {
+                kind: HunkKind::Changed,
+                before: 5..6,
+                after: 3..4,
+            },
+        ];
+        let mut stats = DiffStats::new(&hunks);
+        stats.add_changed_runs(3, &runs(0..4));
+        stats.add_changed_runs(4, &[0..1, 2..4]);
+        stats.add_changed_runs(5, &runs(2..3));
+        stats.finish((4, 6), false);
+        assert_eq!(
+            DiffStats {
+                added_rows: 0,
+                removed_rows: 2,
+                common_rows: 3,
+                changed_rows: 1,
+                moved_rows: 0,
+                changed_pixels: 8,
+                changed_percentage: 100.0 / 3.0,
+                bounds: Some(Rect::new(0, 3, 4, 3)),
+                identical: false,
+                approximate: false,
+            },
+            stats
+        );
+    }
+
+    #[test]
+    fn should_be_identical_without_changes() {
+        let hunks = [Hunk {
+            kind: HunkKind::Common,
+            before: 0..2,
+            after: 0..2,
+        }];
+        let mut stats = DiffStats::new(&hunks);
+        stats.finish((3, 2), true);
+        assert!(stats.identical);
+        assert_eq!(None, stats.bounds);
+        assert_eq!(0.0, stats.changed_percentage);
+        // Images of different sizes are never identical
+        stats.finish((3, 2), false);
+        assert!(!stats.identical);
+    }
+}
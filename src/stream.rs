@@ -0,0 +1,114 @@
+use crate::{render_row, Canvas, DiffOptions};
+use std::borrow::Cow;
+use std::ops::Range;
+use std::vec;
+
+/// What a [`DiffRow`] shows
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RowKind {
+    /// A row found in both images
+    Common,
+    /// A row only found in the after image
+    Added,
+    /// A row only found in the before image
+    Removed,
+    /// A row of the before image replaced by a similar row of the after
+    /// image, shown with its changed pixels highlighted
+    Changed,
+    /// A moved row, where it was in the before image
+    MovedFrom,
+    /// A moved row, where it is in the after image
+    MovedTo,
+    /// A row of the band standing for common rows collapsed by
+    /// [`context_rows`](crate::DiffOptionsBuilder::context_rows)
+    Collapsed,
+}
+
+/// A row of the diff image, or a column when diffing
+/// [`Direction::Columns`](crate::Direction::Columns).
+///
+/// `before` and `after` are the rows of each image it shows. The range of the
+/// image a row is missing from is empty, and starts where the row would be.
+/// Collapsed rows cover every row of the stretch they stand for.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DiffRow {
+    pub kind: RowKind,
+    pub before: Range<u32>,
+    pub after: Range<u32>,
+    /// RGBA pixels of the row
+    pub pixels: Vec<u8>,
+}
+
+// A row of the diff image yet to be painted
+#[derive(Clone, Debug)]
+pub(crate) struct RowOp {
+    pub(crate) kind: RowKind,
+    pub(crate) before: Range<u32>,
+    pub(crate) after: Range<u32>,
+}
+
+/// The rows of a diff image, painted one at a time as they are iterated, see
+/// [`diff_rows`](crate::diff_rows).
+pub struct DiffRows<'a> {
+    canvas: Canvas<'a, u8>,
+    options: Cow<'a, DiffOptions>,
+    ops: vec::IntoIter<RowOp>,
+    height: u32,
+    approximate: bool,
+}
+
+impl<'a> DiffRows<'a> {
+    pub(crate) fn new(
+        canvas: Canvas<'a, u8>,
+        options: Cow<'a, DiffOptions>,
+        ops: Vec<RowOp>,
+        approximate: bool,
+    ) -> Self {
+        let height = ops.len() as u32;
+        DiffRows {
+            canvas,
+            options,
+            ops: ops.into_iter(),
+            height,
+            approximate,
+        }
+    }
+
+    /// Width of the diff image, in pixels
+    pub fn width(&self) -> u32 {
+        self.canvas.width
+    }
+
+    /// Height of the diff image, counting the rows already iterated
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    /// Whether the rows were aligned approximately, see
+    /// [`DiffStats::approximate`](crate::DiffStats::approximate)
+    pub fn approximate(&self) -> bool {
+        self.approximate
+    }
+}
+
+impl Iterator for DiffRows<'_> {
+    type Item = DiffRow;
+
+    fn next(&mut self) -> Option<DiffRow> {
+        let op = self.ops.next()?;
+        let mut pixels = vec![0; self.canvas.width as usize * 4];
+        render_row(&mut pixels, &op, &self.canvas, &self.options);
+        Some(DiffRow {
+            kind: op.kind,
+            before: op.before,
+            after: op.after,
+            pixels,
+        })
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ops.size_hint()
+    }
+}
+
+impl ExactSizeIterator for DiffRows<'_> {}